@@ -1,13 +1,55 @@
 // programs/solana-launchpad/src/lib.rs
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    ed25519_program,
+    hash::hashv,
+    native_token::LAMPORTS_PER_SOL,
+    sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
+};
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{self, Mint, Token, TokenAccount, Transfer},
+    associated_token::{self, get_associated_token_address, AssociatedToken},
+    token::{self, CloseAccount, Mint, SyncNative, Token, TokenAccount, Transfer},
 };
 
 // Replace this with the program ID you got from the solana address command
 declare_id!("AjUxmZYjhXbJq5yDDvxe8Hh2amWnAjLN2Wmf5oET8mZ1");
 
+pub const VAULT_SEED: &[u8] = b"vault";
+pub const CONTRIBUTION_SEED: &[u8] = b"contribution";
+pub const COMMITMENT_SEED: &[u8] = b"commitment";
+pub const VESTING_SEED: &[u8] = b"vesting";
+pub const ALLOWED_MINT_SEED: &[u8] = b"allowed_mint";
+pub const INVESTOR_SEED: &[u8] = b"investor";
+pub const BID_SEED: &[u8] = b"bid";
+pub const VERIFICATION_SEED: &[u8] = b"verification";
+pub const PENDING_ACTION_SEED: &[u8] = b"pending_action";
+pub const ROUND_POSITION_SEED: &[u8] = b"round_position";
+pub const ROUND_VAULT_SEED: &[u8] = b"round_vault";
+pub const PAYMENT_POSITION_SEED: &[u8] = b"payment_position";
+pub const STAKE_SEED: &[u8] = b"stake";
+pub const MAX_ACCEPTED_PAYMENTS: usize = 4;
+pub const MAX_ROUNDS: usize = 32;
+
+// Most remaining accounts a batch instruction will process, so oversized
+// batches fail upfront instead of running out of compute midway
+pub const MAX_BATCH: usize = 4;
+// Most schedules investor_positions summarizes; keeps the result under 1 KiB of return data
+pub const MAX_POSITIONS: usize = 16;
+// Largest mint decimals a sale accepts; u64 amounts scaled by 10^18 still fit in u128
+pub const MAX_TOKEN_DECIMALS: u8 = 18;
+
+// Schema version stamped on new accounts; bump when account layouts change
+pub const ACCOUNT_VERSION: u8 = 1;
+
+// Furthest into the future a round may be scheduled
+pub const MAX_ROUND_HORIZON: i64 = 10 * 365 * 86400;
+
+// How long after a failed sale is finalized before the admin may reclaim it
+pub const ABANDONED_RECLAIM_DELAY: i64 = 365 * 86400;
+
+// Shortest refund window a sale may set until the admin changes it
+pub const DEFAULT_MIN_REFUND_WINDOW: i64 = 7 * 86400;
+
 #[program]
 pub mod solana_launchpad {
     use super::*;
@@ -17,6 +59,278 @@ pub mod solana_launchpad {
         let launchpad = &mut ctx.accounts.launchpad;
         launchpad.admin = *ctx.accounts.admin.key;
         launchpad.total_projects = 0;
+        launchpad.treasury = *ctx.accounts.admin.key;
+        launchpad.min_round_lead = 0;
+        launchpad.curated = false;
+        launchpad.claim_cooldown = 0;
+        launchpad.block_self_purchase = false;
+        launchpad.fee_bps = 0;
+        launchpad.total_investors = 0;
+        launchpad.max_total_investors = 0;
+        launchpad.min_price_per_token = 0;
+        launchpad.version = ACCOUNT_VERSION;
+        launchpad.refund_fee_bps = 0;
+        launchpad.timelock_delay = 0;
+        launchpad.max_vesting_duration = 0;
+        launchpad.min_refund_window = DEFAULT_MIN_REFUND_WINDOW;
+
+        emit!(AdminChanged {
+            launchpad: launchpad.key(),
+            old_admin: Pubkey::default(),
+            new_admin: launchpad.admin,
+        });
+        Ok(())
+    }
+
+    // Set the share of each refund or cancelled purchase kept as a fee (0 disables)
+    pub fn set_refund_fee_bps(ctx: Context<UpdateLaunchpad>, refund_fee_bps: u16) -> Result<()> {
+        let action = AdminAction::SetRefundFeeBps { refund_fee_bps };
+        action.validate()?;
+
+        let launchpad = &mut ctx.accounts.launchpad;
+        require!(
+            launchpad.timelock_delay == 0 || refund_fee_bps <= launchpad.refund_fee_bps,
+            LaunchpadError::FeeIncreaseTimelocked
+        );
+        let key = launchpad.key();
+        launchpad.apply(key, &action)
+    }
+
+    // Lengthen the delay on queued admin actions; shortening it must itself be queued
+    pub fn set_timelock_delay(ctx: Context<UpdateLaunchpad>, timelock_delay: i64) -> Result<()> {
+        let action = AdminAction::SetTimelockDelay { timelock_delay };
+        action.validate()?;
+
+        let launchpad = &mut ctx.accounts.launchpad;
+        require!(
+            timelock_delay >= launchpad.timelock_delay,
+            LaunchpadError::TimelockDelayShortened
+        );
+        let key = launchpad.key();
+        launchpad.apply(key, &action)
+    }
+
+    // Queue a sensitive admin change to take effect after the timelock delay
+    pub fn queue_action(ctx: Context<QueueAction>, action: AdminAction) -> Result<()> {
+        action.validate()?;
+        let launchpad = &ctx.accounts.launchpad;
+        let execute_after = Clock::get()?
+            .unix_timestamp
+            .checked_add(launchpad.timelock_delay)
+            .ok_or(LaunchpadError::MathOverflow)?;
+
+        let pending_action = &mut ctx.accounts.pending_action;
+        pending_action.launchpad = launchpad.key();
+        pending_action.action = action;
+        pending_action.execute_after = execute_after;
+        pending_action.bump = ctx.bumps.pending_action;
+
+        emit!(ActionQueued {
+            launchpad: launchpad.key(),
+            action,
+            execute_after,
+        });
+        Ok(())
+    }
+
+    // Apply the queued admin change once its delay has passed
+    pub fn execute_action(ctx: Context<ExecuteAction>) -> Result<()> {
+        let pending_action = &ctx.accounts.pending_action;
+        require!(
+            Clock::get()?.unix_timestamp >= pending_action.execute_after,
+            LaunchpadError::TimelockNotElapsed
+        );
+        let launchpad = &mut ctx.accounts.launchpad;
+        let key = launchpad.key();
+        launchpad.apply(key, &pending_action.action)
+    }
+
+    // Drop a queued admin change without applying it
+    pub fn cancel_action(ctx: Context<ExecuteAction>) -> Result<()> {
+        emit!(ActionCancelled {
+            launchpad: ctx.accounts.launchpad.key(),
+            action: ctx.accounts.pending_action.action,
+        });
+        Ok(())
+    }
+
+    // Set the lowest price a new round may be created at (0 disables)
+    pub fn set_min_price_per_token(
+        ctx: Context<UpdateLaunchpad>,
+        min_price_per_token: u64,
+    ) -> Result<()> {
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::MinPricePerToken,
+            old_value: launchpad.min_price_per_token,
+            new_value: min_price_per_token,
+        });
+        launchpad.min_price_per_token = min_price_per_token;
+        Ok(())
+    }
+
+    // Limit how many distinct investors may buy across the launchpad (0 disables)
+    pub fn set_max_total_investors(
+        ctx: Context<UpdateLaunchpad>,
+        max_total_investors: u64,
+    ) -> Result<()> {
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::MaxTotalInvestors,
+            old_value: launchpad.max_total_investors,
+            new_value: max_total_investors,
+        });
+        launchpad.max_total_investors = max_total_investors;
+        Ok(())
+    }
+
+    // Update the platform fee and the treasury it's paid to in one step
+    pub fn set_fee_config(
+        ctx: Context<UpdateLaunchpad>,
+        fee_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        let action = AdminAction::SetFeeConfig { fee_bps, treasury };
+        action.validate()?;
+
+        // Fee increases and treasury changes have to wait out the timelock
+        // via queue_action
+        let launchpad = &mut ctx.accounts.launchpad;
+        require!(
+            launchpad.timelock_delay == 0 || fee_bps <= launchpad.fee_bps,
+            LaunchpadError::FeeIncreaseTimelocked
+        );
+        require!(
+            launchpad.timelock_delay == 0 || treasury == launchpad.treasury,
+            LaunchpadError::TreasuryChangeTimelocked
+        );
+        let key = launchpad.key();
+        launchpad.apply(key, &action)
+    }
+
+    // Forbid registrants from buying into their own sales
+    pub fn set_block_self_purchase(
+        ctx: Context<UpdateLaunchpad>,
+        block_self_purchase: bool,
+    ) -> Result<()> {
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::BlockSelfPurchase,
+            old_value: launchpad.block_self_purchase.into(),
+            new_value: block_self_purchase.into(),
+        });
+        launchpad.block_self_purchase = block_self_purchase;
+        Ok(())
+    }
+
+    // Set the minimum time between claims on a schedule (0 disables)
+    pub fn set_claim_cooldown(ctx: Context<UpdateLaunchpad>, claim_cooldown: i64) -> Result<()> {
+        require!(claim_cooldown >= 0, LaunchpadError::InvalidCooldown);
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::ClaimCooldown,
+            old_value: launchpad.claim_cooldown as u64,
+            new_value: claim_cooldown as u64,
+        });
+        launchpad.claim_cooldown = claim_cooldown;
+        Ok(())
+    }
+
+    // Restrict registration to admin-allowed mints
+    pub fn set_curated(ctx: Context<UpdateLaunchpad>, curated: bool) -> Result<()> {
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::Curated,
+            old_value: launchpad.curated.into(),
+            new_value: curated.into(),
+        });
+        launchpad.curated = curated;
+        Ok(())
+    }
+
+    // Sign off on a sale so its rounds may go live on a curated launchpad
+    pub fn approve_sale(ctx: Context<ApproveSale>) -> Result<()> {
+        ctx.accounts.token_sale.approved = true;
+        emit!(SaleApproved {
+            launchpad: ctx.accounts.launchpad.key(),
+            sale: ctx.accounts.token_sale.key(),
+        });
+        Ok(())
+    }
+
+    // Add a mint to the curated allowlist
+    pub fn allow_mint(ctx: Context<AllowMint>) -> Result<()> {
+        let allowed_mint = &mut ctx.accounts.allowed_mint;
+        allowed_mint.launchpad = ctx.accounts.launchpad.key();
+        allowed_mint.mint = ctx.accounts.mint.key();
+        allowed_mint.bump = ctx.bumps.allowed_mint;
+        emit!(WhitelistUpdated {
+            launchpad: allowed_mint.launchpad,
+            mint: allowed_mint.mint,
+            allowed: true,
+        });
+        Ok(())
+    }
+
+    // Remove a mint from the curated allowlist
+    pub fn revoke_mint(ctx: Context<RevokeMint>) -> Result<()> {
+        emit!(WhitelistUpdated {
+            launchpad: ctx.accounts.launchpad.key(),
+            mint: ctx.accounts.allowed_mint.mint,
+            allowed: false,
+        });
+        Ok(())
+    }
+
+    // Set the shortest refund window sales may set
+    pub fn set_min_refund_window(
+        ctx: Context<UpdateLaunchpad>,
+        min_refund_window: i64,
+    ) -> Result<()> {
+        require!(min_refund_window >= 0, LaunchpadError::InvalidRefundWindow);
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::MinRefundWindow,
+            old_value: launchpad.min_refund_window as u64,
+            new_value: min_refund_window as u64,
+        });
+        launchpad.min_refund_window = min_refund_window;
+        Ok(())
+    }
+
+    // Set the minimum notice between creating a round and its start (0 disables)
+    pub fn set_min_round_lead(ctx: Context<UpdateLaunchpad>, min_round_lead: i64) -> Result<()> {
+        require!(min_round_lead >= 0, LaunchpadError::InvalidLeadTime);
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::MinRoundLead,
+            old_value: launchpad.min_round_lead as u64,
+            new_value: min_round_lead as u64,
+        });
+        launchpad.min_round_lead = min_round_lead;
+        Ok(())
+    }
+
+    // Cap vesting durations configured on rounds and grants (0 disables)
+    pub fn set_max_vesting_duration(
+        ctx: Context<UpdateLaunchpad>,
+        max_vesting_duration: u64,
+    ) -> Result<()> {
+        let launchpad = &mut ctx.accounts.launchpad;
+        emit!(LaunchpadSettingChanged {
+            launchpad: launchpad.key(),
+            setting: LaunchpadSetting::MaxVestingDuration,
+            old_value: launchpad.max_vesting_duration,
+            new_value: max_vesting_duration,
+        });
+        launchpad.max_vesting_duration = max_vesting_duration;
         Ok(())
     }
 
@@ -26,145 +340,2907 @@ pub mod solana_launchpad {
         soft_cap: u64,
         hard_cap: u64,
         token_mint: Pubkey,
+        finalization_grace: i64,
+        kyc_authority: Pubkey,
+        terms_hash: [u8; 32],
     ) -> Result<()> {
+        // Sentinel mints can't be sold, wrapped SOL is what buyers pay with
+        require!(
+            token_mint != Pubkey::default() && token_mint != token::spl_token::native_mint::ID,
+            LaunchpadError::InvalidMint
+        );
+        require!(
+            ctx.accounts.token_mint.decimals <= MAX_TOKEN_DECIMALS,
+            LaunchpadError::UnsupportedDecimals
+        );
+        if ctx.accounts.launchpad.curated {
+            require!(
+                ctx.accounts.allowed_mint.is_some(),
+                LaunchpadError::MintNotAllowed
+            );
+        }
+
         let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.launchpad = ctx.accounts.launchpad.key();
         token_sale.registrant = *ctx.accounts.registrant.key;
         token_sale.soft_cap = soft_cap;
         token_sale.hard_cap = hard_cap;
         token_sale.token_mint = token_mint;
         token_sale.total_raised = 0;
         token_sale.is_active = false;
+        token_sale.status = SaleStatus::Active;
+        token_sale.end_time = 0;
+        token_sale.finalization_grace = finalization_grace;
+        token_sale.active_rounds = 0;
+        token_sale.kyc_authority = kyc_authority;
+        token_sale.finalized_at = 0;
+        token_sale.accepted_payments = Vec::new();
+        token_sale.vault_bump = ctx.bumps.vault;
+        token_sale.withdrawn = 0;
+        token_sale.total_refunded = 0;
+        token_sale.total_committed = 0;
+        token_sale.committed_settled = 0;
+        token_sale.withdraw_stream_duration = 0;
+        token_sale.tokens_deposited = 0;
+        token_sale.cooloff_secs = 0;
+        token_sale.round_keys = Vec::new();
+        token_sale.transferable = false;
+        token_sale.tokens_sold = 0;
+        token_sale.deposit_deadline = 0;
+        token_sale.auto_finalize_on_hardcap = false;
+        token_sale.default_vesting = VestingTerms::default();
+        token_sale.claims_enabled_after_finalize = false;
+        token_sale.token_hard_cap = 0;
+        token_sale.version = ACCOUNT_VERSION;
+        token_sale.softcap_extension = 0;
+        token_sale.softcap_extended = false;
+        token_sale.require_verification = false;
+        token_sale.points_mint = Pubkey::default();
+        token_sale.tokens_per_point = 0;
+        token_sale.closed_rounds_raised = 0;
+        token_sale.closed_rounds_sold = 0;
+        token_sale.pending_registrant = Pubkey::default();
+        token_sale.claim_to_self_only = false;
+        token_sale.wrap_sol = false;
+        token_sale.reentry_allowed = true;
+        token_sale.terms_hash = terms_hash;
+        token_sale.enforce_terms_hash = false;
+        token_sale.token_decimals = ctx.accounts.token_mint.decimals;
+        token_sale.min_tokens_sold = 0;
+        token_sale.success_criteria = SuccessCriteria::All;
+        token_sale.custody_authority = Pubkey::default();
+        token_sale.direct_custody = false;
+        token_sale.created_at = Clock::get()?.unix_timestamp;
+        token_sale.state = SaleState::Open;
+        token_sale.approved = false;
+        token_sale.tokens_claimed = 0;
+        token_sale.refund_window = 0;
+        token_sale.active_topup_allowed = false;
+        token_sale.allow_overlapping_rounds = true;
+        token_sale.max_claim_per_tx = 0;
+        token_sale.ata_payer = AtaPayer::Investor;
+        token_sale.velocity_limit = 0;
+        token_sale.velocity_window = 0;
+        token_sale.window_start = 0;
+        token_sale.window_raised = 0;
+        token_sale.payment_value_raised = 0;
+        token_sale.committed_reserved = 0;
+        token_sale.isolated_raised = 0;
+        token_sale.tokens_granted = 0;
+
+        fund_vault_rent(
+            &ctx.accounts.registrant,
+            &ctx.accounts.vault,
+            &ctx.accounts.system_program,
+        )?;
 
         ctx.accounts.launchpad.total_projects += 1;
         Ok(())
     }
 
-    // Add a new sale round
-    pub fn add_sale_round(
-        ctx: Context<AddSaleRound>,
-        price_per_token: u64,
-        tokens_available: u64,
-        min_contribution: u64,
-        max_contribution: u64,
-        start_time: i64,
-        end_time: i64,
-    ) -> Result<()> {
-        let sale_round = &mut ctx.accounts.sale_round;
-        sale_round.price_per_token = price_per_token;
-        sale_round.tokens_available = tokens_available;
-        sale_round.tokens_sold = 0;
-        sale_round.min_contribution = min_contribution;
-        sale_round.max_contribution = max_contribution;
-        sale_round.start_time = start_time;
-        sale_round.end_time = end_time;
-        sale_round.is_active = false;
+    // Top up a sale vault to rent exemption so small transfers into it never fail
+    pub fn init_vault(ctx: Context<InitVault>) -> Result<()> {
+        fund_vault_rent(
+            &ctx.accounts.payer,
+            &ctx.accounts.vault,
+            &ctx.accounts.system_program,
+        )
+    }
+
+    // Deposit sale tokens, optionally making them available in a round
+    pub fn deposit_sale_tokens(ctx: Context<DepositSaleTokens>, amount: u64) -> Result<()> {
+        require!(amount > 0, LaunchpadError::ZeroAmount);
+
+        if let Some(sale_round) = &mut ctx.accounts.sale_round {
+            let token_sale = &ctx.accounts.token_sale;
+            require!(
+                token_sale.status == SaleStatus::Active,
+                LaunchpadError::SaleNotActive
+            );
+            require!(
+                !sale_round.is_active || token_sale.active_topup_allowed,
+                LaunchpadError::ActiveTopUpBlocked
+            );
+            require!(
+                Clock::get()?.unix_timestamp < sale_round.end_time,
+                LaunchpadError::RoundEnded
+            );
+            sale_round.tokens_available = sale_round
+                .tokens_available
+                .checked_add(amount)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            // The round can't offer more than the sale may still sell
+            if token_sale.token_hard_cap > 0 {
+                require!(
+                    token_sale
+                        .tokens_sold
+                        .saturating_add(sale_round.tokens_available)
+                        <= token_sale.token_hard_cap,
+                    LaunchpadError::TokenHardCapReached
+                );
+            }
+        }
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.registrant_token_account.to_account_info(),
+                to: ctx.accounts.vault_token_account.to_account_info(),
+                authority: ctx.accounts.registrant.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, amount)?;
 
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.tokens_deposited = token_sale
+            .tokens_deposited
+            .checked_add(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
         Ok(())
     }
 
-    // Activate a sale round
-    pub fn activate_sale_round(ctx: Context<ActivateSaleRound>) -> Result<()> {
-        let sale_round = &mut ctx.accounts.sale_round;
-        sale_round.is_active = true;
+    // Open the account tracking what an investor paid in one accepted SPL
+    // token, needed to buy with it
+    pub fn open_payment_position(ctx: Context<OpenPaymentPosition>) -> Result<()> {
+        let payment_mint = ctx.accounts.payment_mint.key();
+        require!(
+            ctx.accounts
+                .token_sale
+                .accepted_payments
+                .iter()
+                .any(|p| p.mint == payment_mint),
+            LaunchpadError::UnsupportedPaymentMint
+        );
+        let payment_position = &mut ctx.accounts.payment_position;
+        payment_position.token_sale = ctx.accounts.token_sale.key();
+        payment_position.investor = ctx.accounts.investor.key();
+        payment_position.mint = payment_mint;
+        payment_position.amount = 0;
+        payment_position.value = 0;
+        payment_position.bump = ctx.bumps.payment_position;
         Ok(())
     }
 
-    // Purchase tokens
-    pub fn purchase_tokens(ctx: Context<PurchaseTokens>, amount: u64) -> Result<()> {
-        let sale_round = &mut ctx.accounts.sale_round;
+    // Withdraw everything raised in one accepted SPL token from a succeeded sale
+    pub fn withdraw_payments(ctx: Context<WithdrawPayments>) -> Result<()> {
+        let token_sale = &ctx.accounts.token_sale;
+        require!(
+            token_sale.status == SaleStatus::Succeeded,
+            LaunchpadError::SaleNotSucceeded
+        );
+        require!(
+            token_sale.tokens_deposited >= token_sale.tokens_owed(),
+            LaunchpadError::VaultUnderfunded
+        );
+        // Paid to the custody authority when one is set, like SOL withdrawals
+        let payee = if token_sale.custody_authority == Pubkey::default() {
+            token_sale.registrant
+        } else {
+            token_sale.custody_authority
+        };
+        require_keys_eq!(
+            ctx.accounts.destination.owner,
+            payee,
+            LaunchpadError::InvalidCustody
+        );
+        let amount = ctx.accounts.vault_payment_account.amount;
+        require!(amount > 0, LaunchpadError::NothingToWithdraw);
+
+        let token_sale_key = token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[token_sale.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_payment_account.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)
+    }
+
+    // Return what an investor paid in one SPL token to a failed sale
+    pub fn refund_payment(ctx: Context<RefundPayment>) -> Result<()> {
         let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.status == SaleStatus::Failed,
+            LaunchpadError::SaleNotFailed
+        );
+        token_sale.check_refund_window(Clock::get()?.unix_timestamp)?;
+
+        let payment_position = &ctx.accounts.payment_position;
+        token_sale.payment_value_raised = token_sale
+            .payment_value_raised
+            .saturating_sub(payment_position.value);
+
+        let token_sale_key = token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[token_sale.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_payment_account.to_account_info(),
+                to: ctx.accounts.investor_payment_account.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, payment_position.amount)
+    }
 
-        // Validate contribution
+    // Set the SPL tokens accepted as payment alongside SOL
+    pub fn set_accepted_payments(
+        ctx: Context<UpdateSale>,
+        accepted_payments: Vec<PaymentConfig>,
+    ) -> Result<()> {
+        require!(
+            accepted_payments.len() <= MAX_ACCEPTED_PAYMENTS,
+            LaunchpadError::TooManyPayments
+        );
         require!(
-            amount >= sale_round.min_contribution,
-            LaunchpadError::ContributionTooLow
+            accepted_payments.iter().all(|p| p.price_per_token > 0),
+            LaunchpadError::InvalidPrice
         );
+
+        ctx.accounts.token_sale.accepted_payments = accepted_payments;
+        Ok(())
+    }
+
+    // Let buyers cancel purchases for `cooloff_secs` after making them (0 disables)
+    pub fn set_cooloff(ctx: Context<UpdateSale>, cooloff_secs: i64) -> Result<()> {
+        require!(cooloff_secs >= 0, LaunchpadError::InvalidCooloff);
+        ctx.accounts.token_sale.cooloff_secs = cooloff_secs;
+        Ok(())
+    }
+
+    // Give the registrant `deposit_deadline` seconds after finalization to cover
+    // every token sold, after which investors may take their SOL back (0 disables)
+    pub fn set_deposit_deadline(ctx: Context<UpdateSale>, deposit_deadline: i64) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
         require!(
-            amount <= sale_round.max_contribution,
-            LaunchpadError::ContributionExceeded
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
         );
         require!(
-            token_sale.total_raised + amount <= token_sale.hard_cap,
-            LaunchpadError::HardCapReached
+            deposit_deadline >= 0,
+            LaunchpadError::InvalidDepositDeadline
         );
+        token_sale.deposit_deadline = deposit_deadline;
+        Ok(())
+    }
 
-        // Calculate tokens
-        let tokens = amount
-            .checked_mul(10u64.pow(9)) // Assuming 9 decimals
-            .unwrap()
-            .checked_div(sale_round.price_per_token)
-            .unwrap();
+    // Finalize the sale as soon as a purchase fills the hard cap
+    pub fn set_auto_finalize_on_hardcap(
+        ctx: Context<UpdateSale>,
+        auto_finalize_on_hardcap: bool,
+    ) -> Result<()> {
+        ctx.accounts.token_sale.auto_finalize_on_hardcap = auto_finalize_on_hardcap;
+        Ok(())
+    }
 
+    // Wind the sale down automatically when more than `velocity_limit` SOL
+    // arrives within `velocity_window` seconds
+    pub fn set_velocity_limit(
+        ctx: Context<UpdateSale>,
+        velocity_limit: u64,
+        velocity_window: i64,
+    ) -> Result<()> {
         require!(
-            tokens <= sale_round.tokens_available,
-            LaunchpadError::InsufficientTokens
+            velocity_limit == 0 || velocity_window > 0,
+            LaunchpadError::InvalidVelocityWindow
         );
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.velocity_limit = velocity_limit;
+        token_sale.velocity_window = velocity_window;
+        token_sale.window_start = 0;
+        token_sale.window_raised = 0;
+        Ok(())
+    }
 
-        // Update state
-        sale_round.tokens_available -= tokens;
-        sale_round.tokens_sold += tokens;
-        token_sale.total_raised += amount;
+    // Choose who sponsors the investor's token account on purchase
+    pub fn set_ata_payer(ctx: Context<UpdateSale>, ata_payer: AtaPayer) -> Result<()> {
+        ctx.accounts.token_sale.ata_payer = ata_payer;
+        Ok(())
+    }
 
-        // Transfer SOL to vault
-        let cpi_context = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            anchor_lang::system_program::Transfer {
-                from: ctx.accounts.investor.to_account_info(),
-                to: ctx.accounts.vault.to_account_info(),
-            },
+    // Spread large claims over several transactions
+    pub fn set_max_claim_per_tx(ctx: Context<UpdateSale>, max_claim_per_tx: u64) -> Result<()> {
+        ctx.accounts.token_sale.max_claim_per_tx = max_claim_per_tx;
+        Ok(())
+    }
+
+    // Allow or forbid more than one active round at a time
+    pub fn set_allow_overlapping_rounds(
+        ctx: Context<UpdateSale>,
+        allow_overlapping_rounds: bool,
+    ) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            allow_overlapping_rounds || token_sale.active_rounds <= 1,
+            LaunchpadError::RoundOverlap
         );
-        anchor_lang::system_program::transfer(cpi_context, amount)?;
+        token_sale.allow_overlapping_rounds = allow_overlapping_rounds;
+        Ok(())
+    }
 
-        // Initialize vesting schedule
-        let vesting = &mut ctx.accounts.vesting;
-        vesting.investor = *ctx.accounts.investor.key;
-        vesting.total_allocation = tokens;
-        vesting.released = 0;
-        vesting.start_time = Clock::get()?.unix_timestamp;
-        vesting.duration = 30 * 86400; // 30 days in seconds
+    // Let deposits top up rounds that are already active
+    pub fn set_active_topup_allowed(
+        ctx: Context<UpdateSale>,
+        active_topup_allowed: bool,
+    ) -> Result<()> {
+        ctx.accounts.token_sale.active_topup_allowed = active_topup_allowed;
+        Ok(())
+    }
 
+    // Cap the tokens sold across all rounds (0 disables)
+    pub fn set_token_hard_cap(ctx: Context<UpdateSale>, token_hard_cap: u64) -> Result<()> {
+        ctx.accounts.token_sale.token_hard_cap = token_hard_cap;
         Ok(())
     }
 
-    // Claim vested tokens
-    pub fn claim_tokens(ctx: Context<ClaimTokens>) -> Result<()> {
-        let vesting = &mut ctx.accounts.vesting;
+    // Nominate a new registrant; the change takes effect once they accept.
+    // Nominating the default pubkey cancels a pending transfer
+    pub fn transfer_registrant(ctx: Context<UpdateSale>, new_registrant: Pubkey) -> Result<()> {
+        ctx.accounts.token_sale.pending_registrant = new_registrant;
+        Ok(())
+    }
 
-        let current_time = Clock::get()?.unix_timestamp;
-        let elapsed = current_time - vesting.start_time;
+    // Take over a sale as its nominated registrant
+    pub fn accept_registrant(ctx: Context<AcceptRegistrant>) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        let new_registrant = ctx.accounts.new_registrant.key();
+        require!(
+            token_sale.pending_registrant != Pubkey::default()
+                && token_sale.pending_registrant == new_registrant,
+            LaunchpadError::NotPendingRegistrant
+        );
 
-        require!(elapsed >= 0, LaunchpadError::VestingNotStarted);
+        let previous = token_sale.registrant;
+        token_sale.registrant = new_registrant;
+        token_sale.pending_registrant = Pubkey::default();
+        emit!(RegistrantTransferred {
+            sale: token_sale.key(),
+            previous,
+            registrant: new_registrant,
+        });
+        Ok(())
+    }
 
-        let vested_amount = if elapsed >= vesting.duration as i64 {
-            vesting.total_allocation - vesting.released
-        } else {
-            vesting.total_allocation * elapsed as u64 / vesting.duration
-        };
+    // Hold SOL contributions as wSOL in the vault's native-mint ATA, like
+    // SPL payments. Only switchable before anything has been raised
+    pub fn set_wrap_sol(ctx: Context<UpdateSale>, wrap_sol: bool) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.total_raised == 0,
+            LaunchpadError::SaleHasContributions
+        );
+        require!(
+            !(wrap_sol && token_sale.direct_custody),
+            LaunchpadError::InvalidCustody
+        );
+        token_sale.wrap_sol = wrap_sol;
+        Ok(())
+    }
 
-        require!(vested_amount > 0, LaunchpadError::NothingToClaim);
+    // Pay withdrawals to a custody account such as a multisig, and optionally
+    // route purchases straight there. Direct custody leaves nothing in the vault,
+    // so refunds and cancellations rely on custody returning the funds.
+    pub fn set_custody(
+        ctx: Context<UpdateSale>,
+        custody_authority: Pubkey,
+        direct_custody: bool,
+    ) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.total_raised == 0,
+            LaunchpadError::SaleHasContributions
+        );
+        require!(
+            !direct_custody || (custody_authority != Pubkey::default() && !token_sale.wrap_sol),
+            LaunchpadError::InvalidCustody
+        );
+        token_sale.custody_authority = custody_authority;
+        token_sale.direct_custody = direct_custody;
+        Ok(())
+    }
 
-        // Transfer tokens
-        let transfer_ctx = CpiContext::new(
+    // Unwrap a finished sale's wSOL back into the vault so withdrawals and
+    // refunds pay out of its lamports as usual
+    pub fn unwrap_vault_sol(ctx: Context<UnwrapVaultSol>) -> Result<()> {
+        require!(
+            ctx.accounts.token_sale.status != SaleStatus::Active,
+            LaunchpadError::SaleNotFinalized
+        );
+
+        let token_sale_key = ctx.accounts.token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[ctx.accounts.token_sale.vault_bump],
+        ];
+        token::close_account(CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.vault_token_account.to_account_info(),
-                to: ctx.accounts.investor_token_account.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.vault_wsol_account.to_account_info(),
+                destination: ctx.accounts.vault.to_account_info(),
                 authority: ctx.accounts.vault.to_account_info(),
             },
+            &[&seeds[..]],
+        ))
+    }
+
+    // Require buyers to echo the registered terms hash with every purchase
+    pub fn set_enforce_terms_hash(
+        ctx: Context<UpdateSale>,
+        enforce_terms_hash: bool,
+    ) -> Result<()> {
+        ctx.accounts.token_sale.enforce_terms_hash = enforce_terms_hash;
+        Ok(())
+    }
+
+    // Require a number of tokens sold for success, alongside or instead of the soft cap
+    pub fn set_min_tokens_sold(
+        ctx: Context<UpdateSale>,
+        min_tokens_sold: u64,
+        success_criteria: SuccessCriteria,
+    ) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
         );
+        token_sale.min_tokens_sold = min_tokens_sold;
+        token_sale.success_criteria = success_criteria;
+        Ok(())
+    }
 
-        token::transfer(transfer_ctx, vested_amount)?;
+    // Wind a sale down (no new purchases, claims and refunds unaffected) or reopen it
+    pub fn set_sale_state(ctx: Context<UpdateSale>, state: SaleState) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        if token_sale.state != state {
+            emit!(PauseToggled {
+                sale: token_sale.key(),
+                paused: state == SaleState::WindingDown,
+            });
+        }
+        token_sale.state = state;
+        Ok(())
+    }
 
-        vesting.released += vested_amount;
+    // Close refunds this many seconds after the sale fails, so it can be
+    // reclaimed once the window has passed; at least the launchpad minimum
+    pub fn set_refund_window(ctx: Context<SetRefundWindow>, refund_window: i64) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        require!(
+            refund_window == 0 || refund_window >= ctx.accounts.launchpad.min_refund_window,
+            LaunchpadError::InvalidRefundWindow
+        );
+        token_sale.refund_window = refund_window;
+        Ok(())
+    }
 
+    // Let investors who cancelled their purchases buy back in
+    pub fn set_reentry_allowed(ctx: Context<UpdateSale>, reentry_allowed: bool) -> Result<()> {
+        ctx.accounts.token_sale.reentry_allowed = reentry_allowed;
         Ok(())
     }
-}
 
-// Accounts and Error handling
-#[error_code]
-pub enum LaunchpadError {
-    #[msg("Contribution too low")]
+    // Only ever pay claims into the investor's own token account
+    pub fn set_claim_to_self_only(
+        ctx: Context<UpdateSale>,
+        claim_to_self_only: bool,
+    ) -> Result<()> {
+        ctx.accounts.token_sale.claim_to_self_only = claim_to_self_only;
+        Ok(())
+    }
+
+    // Only let investors the registrant has verified as human buy
+    pub fn set_require_verification(
+        ctx: Context<UpdateSale>,
+        require_verification: bool,
+    ) -> Result<()> {
+        ctx.accounts.token_sale.require_verification = require_verification;
+        Ok(())
+    }
+
+    // Cap each investor's tokens by their balance of a points/stake mint
+    // (the default pubkey disables weighting)
+    pub fn set_points_weighting(
+        ctx: Context<UpdateSale>,
+        points_mint: Pubkey,
+        tokens_per_point: u64,
+    ) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.points_mint = points_mint;
+        token_sale.tokens_per_point = tokens_per_point;
+        Ok(())
+    }
+
+    // Attest that an investor passed the registrant's anti-bot checks
+    pub fn verify_investor(ctx: Context<VerifyInvestor>, investor: Pubkey) -> Result<()> {
+        let verification = &mut ctx.accounts.verification;
+        verification.token_sale = ctx.accounts.token_sale.key();
+        verification.investor = investor;
+        verification.bump = ctx.bumps.verification;
+        Ok(())
+    }
+
+    // Give a sale that ends below its soft cap one extra stretch (0 disables)
+    pub fn set_softcap_extension(ctx: Context<UpdateSale>, softcap_extension: i64) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        require!(
+            softcap_extension >= 0,
+            LaunchpadError::InvalidSoftcapExtension
+        );
+        token_sale.softcap_extension = softcap_extension;
+        Ok(())
+    }
+
+    // Hold all claims until the sale is finalized
+    pub fn set_claims_enabled_after_finalize(
+        ctx: Context<UpdateSale>,
+        claims_enabled_after_finalize: bool,
+    ) -> Result<()> {
+        ctx.accounts.token_sale.claims_enabled_after_finalize = claims_enabled_after_finalize;
+        Ok(())
+    }
+
+    // Set the vesting terms used by rounds that don't override them
+    pub fn set_default_vesting(ctx: Context<UpdateSale>, terms: VestingTerms) -> Result<()> {
+        terms.validate()?;
+        ctx.accounts
+            .launchpad
+            .check_vesting_duration(terms.duration)?;
+        ctx.accounts.token_sale.default_vesting = terms;
+        Ok(())
+    }
+
+    // Allow investors to reassign their vesting schedules to another wallet
+    pub fn set_transferable(ctx: Context<UpdateSale>, transferable: bool) -> Result<()> {
+        ctx.accounts.token_sale.transferable = transferable;
+        Ok(())
+    }
+
+    // Stream raised funds to the registrant over `duration` seconds after
+    // finalization instead of releasing them at once (0 disables)
+    pub fn set_withdraw_stream(ctx: Context<UpdateSale>, duration: i64) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        require!(duration >= 0, LaunchpadError::InvalidStreamDuration);
+        token_sale.withdraw_stream_duration = duration;
+        Ok(())
+    }
+
+    // Add a new sale round
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_sale_round(
+        ctx: Context<AddSaleRound>,
+        price_per_token: u64,
+        tokens_available: u64,
+        min_contribution: u64,
+        max_contribution: u64,
+        start_time: i64,
+        end_time: i64,
+        rounding: RoundingMode,
+        allowed_tiers: [u64; 4],
+        vesting: Option<VestingTerms>,
+        max_tokens_per_tx: u64,
+        partial_fill_allowed: bool,
+    ) -> Result<()> {
+        let config = RoundConfig {
+            price_per_token,
+            tokens_available,
+            min_contribution,
+            max_contribution,
+            start_time,
+            end_time,
+            rounding,
+            allowed_tiers,
+            vesting,
+            max_tokens_per_tx,
+            partial_fill_allowed,
+        };
+        require!(
+            ctx.accounts.token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleFinalized
+        );
+        config.validate(&ctx.accounts.launchpad)?;
+
+        let sale_round = &mut ctx.accounts.sale_round;
+        sale_round.set_inner(SaleRound::from_config(
+            ctx.accounts.token_sale.key(),
+            &config,
+        ));
+
+        // The sale ends when its last round does
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.end_time = token_sale.end_time.max(end_time);
+
+        require!(
+            token_sale.round_keys.len() < MAX_ROUNDS,
+            LaunchpadError::TooManyRounds
+        );
+        token_sale.round_keys.push(sale_round.key());
+
+        Ok(())
+    }
+
+    // Create several rounds at once. Remaining accounts are the new round
+    // keypairs, one per config and in the same order, each signing the tx.
+    pub fn add_sale_rounds<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddSaleRounds<'info>>,
+        configs: Vec<RoundConfig>,
+    ) -> Result<()> {
+        require!(
+            configs.len() <= MAX_BATCH && ctx.remaining_accounts.len() <= MAX_BATCH,
+            LaunchpadError::BatchTooLarge
+        );
+        require!(
+            !configs.is_empty() && configs.len() == ctx.remaining_accounts.len(),
+            LaunchpadError::InvalidRoundAccounts
+        );
+        require!(
+            ctx.accounts.token_sale.round_keys.len() + configs.len() <= MAX_ROUNDS,
+            LaunchpadError::TooManyRounds
+        );
+        require!(
+            ctx.accounts.token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleFinalized
+        );
+        // Validate everything up front; any failure reverts the whole batch
+        for config in &configs {
+            config.validate(&ctx.accounts.launchpad)?;
+        }
+
+        let token_sale_key = ctx.accounts.token_sale.key();
+        let space = 8 + SaleRound::LEN;
+        let lamports = Rent::get()?.minimum_balance(space);
+        for (config, info) in configs.iter().zip(ctx.remaining_accounts.iter()) {
+            require!(info.is_signer, LaunchpadError::InvalidRoundAccounts);
+            anchor_lang::system_program::create_account(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::CreateAccount {
+                        from: ctx.accounts.registrant.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                lamports,
+                space as u64,
+                &crate::ID,
+            )?;
+            SaleRound::from_config(token_sale_key, config)
+                .try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+            let token_sale = &mut ctx.accounts.token_sale;
+            token_sale.end_time = token_sale.end_time.max(config.end_time);
+            token_sale.round_keys.push(info.key());
+        }
+
+        Ok(())
+    }
+
+    // Update an existing sale round's configuration
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_sale_round(
+        ctx: Context<UpdateSaleRound>,
+        price_per_token: u64,
+        tokens_available: u64,
+        min_contribution: u64,
+        max_contribution: u64,
+        start_time: i64,
+        end_time: i64,
+        rounding: RoundingMode,
+        allowed_tiers: [u64; 4],
+        vesting: Option<VestingTerms>,
+        max_tokens_per_tx: u64,
+        partial_fill_allowed: bool,
+    ) -> Result<()> {
+        // Updated rounds are held to the same launchpad rules as new ones
+        let config = RoundConfig {
+            price_per_token,
+            tokens_available,
+            min_contribution,
+            max_contribution,
+            start_time,
+            end_time,
+            rounding,
+            allowed_tiers,
+            vesting,
+            max_tokens_per_tx,
+            partial_fill_allowed,
+        };
+        config.validate(&ctx.accounts.launchpad)?;
+
+        let sale_round = &mut ctx.accounts.sale_round;
+
+        // Repricing after sales would make tokens_sold inconsistent with the price paid
+        if config.price_per_token != sale_round.price_per_token {
+            require!(sale_round.tokens_sold == 0, LaunchpadError::RoundHasSales);
+        }
+        require!(!sale_round.is_active, LaunchpadError::RoundActive);
+
+        sale_round.price_per_token = config.price_per_token;
+        sale_round.tokens_available = config.tokens_available;
+        sale_round.min_contribution = config.min_contribution;
+        sale_round.max_contribution = config.max_contribution;
+        sale_round.start_time = config.start_time;
+        sale_round.end_time = config.end_time;
+        sale_round.rounding = config.rounding;
+        sale_round.allowed_tiers = config.allowed_tiers;
+        sale_round.vesting = config.vesting;
+        sale_round.max_tokens_per_tx = config.max_tokens_per_tx;
+        sale_round.partial_fill_allowed = config.partial_fill_allowed;
+
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.end_time = token_sale.end_time.max(config.end_time);
+
+        Ok(())
+    }
+
+    // Activate a sale round
+    pub fn activate_sale_round(ctx: Context<ActivateSaleRound>) -> Result<()> {
+        if ctx.accounts.launchpad.curated {
+            require!(
+                ctx.accounts.token_sale.approved,
+                LaunchpadError::SaleNotApproved
+            );
+        }
+        let sale_round = &mut ctx.accounts.sale_round;
+        require!(
+            ctx.accounts.token_sale.tokens_deposited >= sale_round.tokens_available,
+            LaunchpadError::InsufficientVaultInventory
+        );
+        if !sale_round.is_active {
+            let token_sale = &mut ctx.accounts.token_sale;
+            require!(
+                token_sale.allow_overlapping_rounds || token_sale.active_rounds == 0,
+                LaunchpadError::RoundOverlap
+            );
+            sale_round.is_active = true;
+            token_sale.active_rounds = token_sale
+                .active_rounds
+                .checked_add(1)
+                .ok_or(LaunchpadError::MathOverflow)?;
+
+            // Snapshot the terms investors are buying into
+            emit!(RoundActivated {
+                round: sale_round.key(),
+                tokens_available: sale_round.tokens_available,
+                price: sale_round.price_per_token,
+                start: sale_round.start_time,
+                end: sale_round.end_time,
+            });
+        }
+        Ok(())
+    }
+
+    // Deactivate a sale round
+    pub fn deactivate_sale_round(ctx: Context<ActivateSaleRound>) -> Result<()> {
+        let sale_round = &mut ctx.accounts.sale_round;
+        if sale_round.is_active {
+            sale_round.is_active = false;
+            let token_sale = &mut ctx.accounts.token_sale;
+            token_sale.active_rounds = token_sale.active_rounds.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    // Purchase tokens
+    pub fn purchase_tokens(
+        ctx: Context<PurchaseTokens>,
+        requested: u64,
+        terms_hash: Option<[u8; 32]>,
+    ) -> Result<()> {
+        require!(requested > 0, LaunchpadError::ZeroAmount);
+
+        // Create the investor's ATA up front, funded per the sale's ata_payer
+        if ctx.accounts.investor_token_account.data_is_empty() {
+            associated_token::create(CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.ata_payer()?,
+                    associated_token: ctx.accounts.investor_token_account.to_account_info(),
+                    authority: ctx.accounts.investor.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+            ))?;
+        }
+
+        let sale_round = &mut ctx.accounts.sale_round;
+        let token_sale = &mut ctx.accounts.token_sale;
+
+        check_purchase_gates(
+            &ctx.accounts.launchpad,
+            token_sale,
+            sale_round,
+            ctx.accounts.investor.key,
+            ctx.accounts.verification.is_some(),
+            &ctx.accounts.instructions,
+        )?;
+
+        // Several rounds may be open at once; the buyer picks one
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= sale_round.start_time,
+            LaunchpadError::RoundNotStarted
+        );
+        require!(
+            now < token_sale.round_end(sale_round),
+            LaunchpadError::RoundEnded
+        );
+
+        // First purchase anywhere on the launchpad counts a new investor
+        let investor_marker = &mut ctx.accounts.investor_marker;
+        if investor_marker.investor == Pubkey::default() {
+            investor_marker.launchpad = ctx.accounts.launchpad.key();
+            investor_marker.investor = ctx.accounts.investor.key();
+            investor_marker.bump = ctx.bumps.investor_marker;
+
+            let launchpad = &mut ctx.accounts.launchpad;
+            launchpad.total_investors += 1;
+            if launchpad.max_total_investors > 0 {
+                require!(
+                    launchpad.total_investors <= launchpad.max_total_investors,
+                    LaunchpadError::InvestorCapReached
+                );
+            }
+        }
+
+        // A refunded contribution either blocks the investor or reopens for them
+        let contribution = &mut ctx.accounts.contribution;
+        if contribution.refunded {
+            require!(token_sale.reentry_allowed, LaunchpadError::ReentryBlocked);
+            contribution.refunded = false;
+        }
+
+        if token_sale.enforce_terms_hash {
+            require!(
+                terms_hash == Some(token_sale.terms_hash),
+                LaunchpadError::TermsHashMismatch
+            );
+        }
+
+        // Pay in an accepted SPL token when a payment mint is passed, otherwise in SOL
+        let payment_price = match &ctx.accounts.payment_mint {
+            Some(payment_mint) => Some(
+                token_sale
+                    .accepted_payments
+                    .iter()
+                    .find(|p| p.mint == payment_mint.key())
+                    .ok_or(LaunchpadError::UnsupportedPaymentMint)?
+                    .price_per_token,
+            ),
+            None => None,
+        };
+        if let Some(payment_mint) = &ctx.accounts.payment_mint {
+            let payment_position = ctx
+                .accounts
+                .payment_position
+                .as_ref()
+                .ok_or(LaunchpadError::PaymentPositionRequired)?;
+            require_keys_eq!(
+                payment_position.mint,
+                payment_mint.key(),
+                LaunchpadError::PaymentPositionRequired
+            );
+        }
+        // Strategic investors may have a registrant-set SOL price of their own
+        let price_override = ctx.accounts.contribution.price_override;
+        let price = match payment_price {
+            Some(payment_price) => payment_price,
+            None if price_override > 0 => price_override,
+            None => sale_round.price_per_token,
+        };
+
+        // Only charge for what the round can still deliver; allowlisted
+        // investors may also buy their guaranteed tokens out of the reserve
+        let guaranteed = ctx.accounts.contribution.guaranteed_allocation;
+        let available = sale_round.available_to(guaranteed);
+        let mut amount = requested;
+        if sale_round.partial_fill_allowed {
+            let fillable = sale_round.cost_of(available, price, token_sale.token_decimals)?;
+            require!(fillable > 0, LaunchpadError::RoundSoldOut);
+            amount = amount.min(fillable);
+        }
+
+        // Validate contribution (limits and caps are denominated in SOL, so SPL
+        // payments count at what the same tokens cost in SOL)
+        // Failures log the numbers involved so clients can explain them
+        let value = match payment_price {
+            Some(payment_price) => sale_round.sol_value_of(amount, payment_price)?,
+            None => amount,
+        };
+        let first_purchase =
+            ctx.accounts.contribution.amount == 0 && ctx.accounts.contribution.tokens_bought == 0;
+        sale_round.check_contribution(value, first_purchase)?;
+        let remaining = token_sale.remaining_capacity();
+        require!(remaining > 0, LaunchpadError::HardCapReached);
+        if value > remaining {
+            msg!(
+                "Contribution {} exceeds remaining capacity {}",
+                value,
+                remaining
+            );
+            return err!(LaunchpadError::HardCapExceeded);
+        }
+
+        require!(sale_round.accepts_tier(value), LaunchpadError::InvalidTier);
+
+        // Calculate tokens
+        let tokens = sale_round.tokens_for(amount, price, token_sale.token_decimals, available)?;
+        if amount < requested {
+            emit!(PartialFill {
+                sale_round: sale_round.key(),
+                investor: ctx.accounts.investor.key(),
+                requested,
+                filled: amount,
+                tokens,
+            });
+        }
+
+        if sale_round.max_tokens_per_tx > 0 {
+            require!(
+                tokens <= sale_round.max_tokens_per_tx,
+                LaunchpadError::TxTokenLimitExceeded
+            );
+        }
+        if token_sale.token_hard_cap > 0 {
+            require!(
+                token_sale.tokens_sold.saturating_add(tokens) <= token_sale.token_hard_cap,
+                LaunchpadError::TokenHardCapReached
+            );
+        }
+        if let Some(round_position) = &mut ctx.accounts.round_position {
+            round_position.tokens_bought = round_position
+                .tokens_bought
+                .checked_add(tokens)
+                .ok_or(LaunchpadError::MathOverflow)?;
+        }
+        if sale_round.max_alloc_bps > 0 {
+            let bought = ctx
+                .accounts
+                .round_position
+                .as_ref()
+                .ok_or(LaunchpadError::RoundPositionRequired)?
+                .tokens_bought;
+            let max_tokens = sale_round.max_allocation();
+            if bought > max_tokens {
+                msg!(
+                    "Buying {} tokens in the round in total, cap is {}",
+                    bought,
+                    max_tokens
+                );
+                return err!(LaunchpadError::AllocationCapExceeded);
+            }
+        }
+        if token_sale.points_mint != Pubkey::default() {
+            let points_account = ctx
+                .accounts
+                .points_account
+                .as_ref()
+                .ok_or(LaunchpadError::PointsAccountRequired)?;
+            let max_tokens = token_sale.max_tokens_for_points(points_account.amount);
+            let bought = ctx
+                .accounts
+                .contribution
+                .tokens_bought
+                .saturating_add(tokens);
+            if bought > max_tokens {
+                msg!(
+                    "Buying {} tokens in total, points allow {}",
+                    bought,
+                    max_tokens
+                );
+                return err!(LaunchpadError::PointsCapExceeded);
+            }
+        }
+
+        // Update state; guaranteed tokens come out of the reserve first
+        let from_guarantee = tokens.min(guaranteed);
+        ctx.accounts.contribution.guaranteed_allocation -= from_guarantee;
+        sale_round.reserved_tokens -= from_guarantee.min(sale_round.reserved_tokens);
+        sale_round.tokens_available -= tokens;
+        sale_round.tokens_sold += tokens;
+        token_sale.tokens_sold += tokens;
+
+        if payment_price.is_some() {
+            // Transfer payment tokens to the vault's ATA for that mint
+            let (Some(from), Some(to)) = (
+                &ctx.accounts.investor_payment_account,
+                &ctx.accounts.vault_payment_account,
+            ) else {
+                return err!(LaunchpadError::PaymentAccountsMissing);
+            };
+            let transfer_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: from.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: ctx.accounts.investor.to_account_info(),
+                },
+            );
+            token::transfer(transfer_ctx, amount)?;
+
+            // Tracked per mint so it can be refunded in kind if the sale fails
+            let payment_position = ctx
+                .accounts
+                .payment_position
+                .as_mut()
+                .ok_or(LaunchpadError::PaymentPositionRequired)?;
+            payment_position.amount = payment_position
+                .amount
+                .checked_add(amount)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            payment_position.value = payment_position
+                .value
+                .checked_add(value)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            token_sale.payment_value_raised = token_sale
+                .payment_value_raised
+                .checked_add(value)
+                .ok_or(LaunchpadError::MathOverflow)?;
+        } else {
+            token_sale.total_raised = token_sale
+                .total_raised
+                .checked_add(amount)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            sale_round.sol_raised = sale_round
+                .sol_raised
+                .checked_add(amount)
+                .ok_or(LaunchpadError::MathOverflow)?;
+
+            // Circuit breaker: this purchase goes through, later ones are
+            // stopped until the registrant reopens the sale
+            if token_sale.record_inflow(amount, now) {
+                token_sale.state = SaleState::WindingDown;
+                emit!(VelocityBreakerTripped {
+                    sale: token_sale.key(),
+                    window_raised: token_sale.window_raised,
+                    velocity_limit: token_sale.velocity_limit,
+                });
+            }
+
+            // Isolated rounds refund out of their own vault, not the sale's
+            let contribution = &mut ctx.accounts.contribution;
+            if !sale_round.isolated {
+                contribution.amount = contribution
+                    .amount
+                    .checked_add(amount)
+                    .ok_or(LaunchpadError::MathOverflow)?;
+            }
+            contribution.record_spend(amount, tokens)?;
+
+            // Transfer SOL to the round's vault, custody, the sale vault, or
+            // wrap it into the vault's wSOL account
+            let wrap = token_sale.wrap_sol && !sale_round.isolated;
+            let destination = if sale_round.isolated {
+                // Never in the sale vault, so sale withdrawals skip it
+                token_sale.isolated_raised = token_sale
+                    .isolated_raised
+                    .checked_add(amount)
+                    .ok_or(LaunchpadError::MathOverflow)?;
+                ctx.accounts
+                    .round_vault
+                    .as_ref()
+                    .ok_or(LaunchpadError::RoundVaultRequired)?
+                    .to_account_info()
+            } else if token_sale.direct_custody {
+                // Already out of the vault, so there is nothing left to withdraw
+                token_sale.withdrawn = token_sale
+                    .withdrawn
+                    .checked_add(amount)
+                    .ok_or(LaunchpadError::MathOverflow)?;
+                ctx.accounts
+                    .custody
+                    .as_ref()
+                    .ok_or(LaunchpadError::InvalidCustody)?
+                    .to_account_info()
+            } else if wrap {
+                ctx.accounts
+                    .vault_wsol_account
+                    .as_ref()
+                    .ok_or(LaunchpadError::WsolAccountRequired)?
+                    .to_account_info()
+            } else {
+                ctx.accounts.vault.to_account_info()
+            };
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.investor.to_account_info(),
+                    to: destination.clone(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, amount)?;
+            if wrap {
+                token::sync_native(CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    SyncNative {
+                        account: destination,
+                    },
+                ))?;
+            }
+
+            // Platform fee, charged on top of the contribution
+            let fee = (amount as u128 * ctx.accounts.launchpad.fee_bps as u128 / 10_000) as u64;
+            if fee > 0 {
+                let cpi_context = CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.investor.to_account_info(),
+                        to: ctx.accounts.treasury.to_account_info(),
+                    },
+                );
+                anchor_lang::system_program::transfer(cpi_context, fee)?;
+            }
+        }
+
+        // A sale filled to its hard cap, in SOL and accepted tokens alike, is
+        // over; settle it now
+        if token_sale.auto_finalize_on_hardcap
+            && token_sale.remaining_capacity() == 0
+            && token_sale.committed_settled == token_sale.total_committed
+        {
+            token_sale.finalize(now);
+            emit!(token_sale.finalized_event(token_sale.key()));
+        }
+
+        // Initialize vesting schedule, snapshotting the resolved terms so later
+        // round or sale updates can't change them
+        let terms = token_sale.vesting_for(sale_round);
+        ctx.accounts.vesting.record(
+            ctx.accounts.token_sale.key(),
+            ctx.accounts.investor.key(),
+            tokens,
+            price,
+            VestingSource::Purchase,
+            &terms,
+            Clock::get()?.unix_timestamp,
+        );
+        ctx.accounts.vesting.stamp_address(
+            sale_round.key(),
+            ctx.accounts.contribution.vesting_count,
+            ctx.bumps.vesting,
+        );
+        // Remember what was paid in SOL so the purchase can be cancelled
+        ctx.accounts.vesting.paid = if payment_price.is_none() { amount } else { 0 };
+        ctx.accounts.contribution.tokens_bought += tokens;
+        ctx.accounts.contribution.track(
+            ctx.accounts.token_sale.key(),
+            ctx.accounts.investor.key(),
+            ctx.bumps.contribution,
+        )?;
+
+        emit!(TokensPurchased {
+            sale_round: sale_round.key(),
+            investor: ctx.accounts.investor.key(),
+            amount,
+            tokens,
+            price_per_token: price,
+            payment_mint: ctx.accounts.payment_mint.as_ref().map(|mint| mint.key()),
+        });
+
+        Ok(())
+    }
+
+    // Set a per-investor SOL price used instead of the round price (0 clears it)
+    pub fn set_price_override(
+        ctx: Context<SetPriceOverride>,
+        investor: Pubkey,
+        price_override: u64,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.token_sale = ctx.accounts.token_sale.key();
+        contribution.investor = investor;
+        contribution.bump = ctx.bumps.contribution;
+        contribution.price_override = price_override;
+        Ok(())
+    }
+
+    // Allowlist an investor ahead of the sale: a number of tokens they may buy
+    // out of rounds' reserves, and optionally their own SOL price (0 for none)
+    pub fn seed_contribution(
+        ctx: Context<SetPriceOverride>,
+        investor: Pubkey,
+        guaranteed_allocation: u64,
+        price_override: u64,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.token_sale = ctx.accounts.token_sale.key();
+        contribution.investor = investor;
+        contribution.bump = ctx.bumps.contribution;
+        contribution.guaranteed_allocation = guaranteed_allocation;
+        contribution.price_override = price_override;
+        Ok(())
+    }
+
+    // Undo a SOL purchase within the sale's cooling-off window
+    pub fn cancel_purchase(ctx: Context<CancelPurchase>) -> Result<()> {
+        let vesting = &ctx.accounts.vesting;
+        let sale_round = &mut ctx.accounts.sale_round;
+        let token_sale = &mut ctx.accounts.token_sale;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        // Wrapped or custodied payments aren't in the vault's lamports
+        require!(
+            !token_sale.wrap_sol && !token_sale.direct_custody,
+            LaunchpadError::NotCancellable
+        );
+        require!(!sale_round.isolated, LaunchpadError::NotCancellable);
+        require!(
+            vesting.paid > 0 && vesting.released == 0,
+            LaunchpadError::NotCancellable
+        );
+        require!(
+            now < vesting.start_time.saturating_add(token_sale.cooloff_secs),
+            LaunchpadError::CooloffExpired
+        );
+        require!(
+            now < token_sale.round_end(sale_round),
+            LaunchpadError::RoundEnded
+        );
+
+        // Return the allocation to the round and the payment to the investor
+        sale_round.tokens_available += vesting.total_allocation;
+        sale_round.tokens_sold -= vesting.total_allocation;
+        token_sale.tokens_sold -= vesting.total_allocation;
+        token_sale.total_raised = token_sale
+            .total_raised
+            .checked_sub(vesting.paid)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        sale_round.sol_raised = sale_round
+            .sol_raised
+            .checked_sub(vesting.paid)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.amount -= vesting.paid;
+        contribution.tokens_bought -= vesting.total_allocation;
+        // Only SOL purchases count towards the cost basis
+        if vesting.paid > 0 {
+            contribution.total_spent -= vesting.paid;
+            contribution.total_tokens -= vesting.total_allocation;
+        }
+        // Fully cancelled out; purchase checks this against reentry_allowed
+        if contribution.amount == 0 {
+            contribution.refunded = true;
+        }
+
+        let token_sale_key = token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[token_sale.vault_bump],
+        ];
+        pay_refund(
+            &ctx.accounts.vault,
+            &ctx.accounts.investor,
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program,
+            &[&seeds[..]],
+            vesting.paid,
+            ctx.accounts.launchpad.refund_fee_bps,
+        )
+    }
+
+    // Grant vested tokens from the round's inventory without a purchase,
+    // vesting from `start_time` (default now) but never from before the sale
+    pub fn create_vesting_grant(
+        ctx: Context<CreateVestingGrant>,
+        beneficiary: Pubkey,
+        amount: u64,
+        duration: u64,
+        cliff: u64,
+        start_time: Option<i64>,
+    ) -> Result<()> {
+        let start_time = match start_time {
+            Some(start_time) => start_time,
+            None => Clock::get()?.unix_timestamp,
+        };
+        require!(
+            start_time >= ctx.accounts.token_sale.created_at,
+            LaunchpadError::InvalidVestingStart
+        );
+
+        let terms = VestingTerms {
+            duration,
+            cliff,
+            tge_bps: 0,
+            release_interval: 0,
+            claim_interval: 0,
+        };
+        terms.validate()?;
+        ctx.accounts.launchpad.check_vesting_duration(duration)?;
+
+        let sale_round = &mut ctx.accounts.sale_round;
+        require!(
+            sale_round.tokens_available > 0,
+            LaunchpadError::RoundSoldOut
+        );
+        if amount > sale_round.tokens_available {
+            msg!(
+                "Requested {} tokens, {} available",
+                amount,
+                sale_round.tokens_available
+            );
+            return err!(LaunchpadError::InsufficientTokens);
+        }
+        // Grants draw down the reserve first
+        sale_round.tokens_available -= amount;
+        sale_round.reserved_tokens = sale_round.reserved_tokens.saturating_sub(amount);
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.tokens_granted = token_sale
+            .tokens_granted
+            .checked_add(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+
+        ctx.accounts.vesting.record(
+            ctx.accounts.token_sale.key(),
+            beneficiary,
+            amount,
+            0,
+            VestingSource::Grant,
+            &terms,
+            start_time,
+        );
+        ctx.accounts.vesting.stamp_address(
+            sale_round.key(),
+            ctx.accounts.contribution.vesting_count,
+            ctx.bumps.vesting,
+        );
+        ctx.accounts.contribution.track(
+            ctx.accounts.token_sale.key(),
+            beneficiary,
+            ctx.bumps.contribution,
+        )?;
+
+        Ok(())
+    }
+
+    // Commit SOL toward an oversubscribable round, allocated pro-rata after it ends
+    pub fn commit(ctx: Context<Commit>, amount: u64) -> Result<()> {
+        require!(amount > 0, LaunchpadError::ZeroAmount);
+        let sale_round = &mut ctx.accounts.sale_round;
+        check_purchase_gates(
+            &ctx.accounts.launchpad,
+            &ctx.accounts.token_sale,
+            sale_round,
+            ctx.accounts.investor.key,
+            ctx.accounts.verification.is_some(),
+            &ctx.accounts.instructions,
+        )?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= sale_round.start_time,
+            LaunchpadError::RoundNotStarted
+        );
+        require!(
+            now < ctx.accounts.token_sale.round_end(sale_round),
+            LaunchpadError::RoundEnded
+        );
+        sale_round.check_contribution(amount, ctx.accounts.commitment.amount == 0)?;
+
+        ctx.accounts.commitment.add(
+            sale_round.key(),
+            ctx.accounts.investor.key(),
+            amount,
+            ctx.bumps.commitment,
+        )?;
+        sale_round.add_commitment(&mut ctx.accounts.token_sale, amount)?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.investor.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+
+        Ok(())
+    }
+
+    // Set how long before a round ends sealed bids stop and reveals begin;
+    // 0 disables bidding
+    pub fn set_reveal_window(ctx: Context<ConfigureRound>, reveal_window: i64) -> Result<()> {
+        let sale_round = &mut ctx.accounts.sale_round;
+        require!(!sale_round.is_active, LaunchpadError::RoundActive);
+        require!(reveal_window >= 0, LaunchpadError::InvalidRevealWindow);
+        sale_round.reveal_window = reveal_window;
+        Ok(())
+    }
+
+    // Hold back part of the round's inventory for grants only
+    pub fn set_reserved_tokens(ctx: Context<ConfigureRound>, reserved_tokens: u64) -> Result<()> {
+        let sale_round = &mut ctx.accounts.sale_round;
+        require!(
+            reserved_tokens <= sale_round.tokens_available,
+            LaunchpadError::InvalidReserve
+        );
+        sale_round.reserved_tokens = reserved_tokens;
+        Ok(())
+    }
+
+    // Close a round of a finished sale and return its rent to the registrant
+    pub fn close_round(ctx: Context<CloseRound>) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        require!(
+            token_sale.status != SaleStatus::Active,
+            LaunchpadError::SaleNotFinalized
+        );
+        // Unsettled commitments still read the round to allocate
+        require!(
+            token_sale.committed_settled == token_sale.total_committed,
+            LaunchpadError::AllocationsPending
+        );
+
+        // An isolated round's vault must be paid out or refunded first
+        let sale_round = &ctx.accounts.sale_round;
+        require!(
+            !sale_round.isolated || sale_round.vault_withdrawn || sale_round.sol_raised == 0,
+            LaunchpadError::RoundVaultNotEmpty
+        );
+        token_sale.closed_rounds_raised = token_sale
+            .closed_rounds_raised
+            .checked_add(sale_round.sol_raised)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        token_sale.closed_rounds_sold = token_sale
+            .closed_rounds_sold
+            .checked_add(sale_round.tokens_sold)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        let round_key = sale_round.key();
+        token_sale.round_keys.retain(|key| *key != round_key);
+        Ok(())
+    }
+
+    // Cap the total time a round may be extended by
+    pub fn set_max_total_extension(
+        ctx: Context<ConfigureRound>,
+        max_total_extension: i64,
+    ) -> Result<()> {
+        require!(max_total_extension >= 0, LaunchpadError::InvalidExtension);
+        ctx.accounts.sale_round.max_total_extension = max_total_extension;
+        Ok(())
+    }
+
+    // Cap each investor's tokens in the round at a share of its total (0 disables)
+    pub fn set_max_alloc_bps(ctx: Context<ConfigureRound>, max_alloc_bps: u16) -> Result<()> {
+        require!(
+            max_alloc_bps <= 10_000,
+            LaunchpadError::InvalidAllocationCap
+        );
+        ctx.accounts.sale_round.max_alloc_bps = max_alloc_bps;
+        Ok(())
+    }
+
+    // Give a round its own vault and soft cap so its funds settle apart from
+    // the rest of the sale
+    pub fn enable_round_vault(ctx: Context<EnableRoundVault>, soft_cap: u64) -> Result<()> {
+        require!(
+            ctx.accounts.token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+        let sale_round = &mut ctx.accounts.sale_round;
+        require!(
+            sale_round.tokens_sold == 0 && sale_round.sol_raised == 0,
+            LaunchpadError::RoundHasPurchases
+        );
+        sale_round.isolated = true;
+        sale_round.vault_bump = ctx.bumps.round_vault;
+        sale_round.soft_cap = soft_cap;
+
+        fund_vault_rent(
+            &ctx.accounts.registrant,
+            &ctx.accounts.round_vault,
+            &ctx.accounts.system_program,
+        )
+    }
+
+    // Open the account tracking an investor's tokens in one round, needed to
+    // buy into rounds with a percentage allocation cap
+    pub fn open_round_position(ctx: Context<OpenRoundPosition>) -> Result<()> {
+        let round_position = &mut ctx.accounts.round_position;
+        round_position.sale_round = ctx.accounts.sale_round.key();
+        round_position.investor = ctx.accounts.investor.key();
+        round_position.tokens_bought = 0;
+        round_position.bump = ctx.bumps.round_position;
+        Ok(())
+    }
+
+    // Push a running round's end back, within its extension allowance
+    pub fn extend_round(ctx: Context<ConfigureRound>, extension: i64) -> Result<()> {
+        require!(extension > 0, LaunchpadError::InvalidExtension);
+        let sale_round = &mut ctx.accounts.sale_round;
+        require!(
+            Clock::get()?.unix_timestamp < sale_round.end_time,
+            LaunchpadError::RoundEnded
+        );
+
+        let total_extended = sale_round
+            .total_extended
+            .checked_add(extension)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        require!(
+            total_extended <= sale_round.max_total_extension,
+            LaunchpadError::ExtensionLimitReached
+        );
+        let end_time = sale_round
+            .end_time
+            .checked_add(extension)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        validate_round_times(sale_round.start_time, end_time)?;
+
+        sale_round.total_extended = total_extended;
+        sale_round.end_time = end_time;
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.end_time = token_sale.end_time.max(end_time);
+        Ok(())
+    }
+
+    // Commit to a hidden bid, sha256(amount LE || nonce || investor)
+    pub fn commit_bid(ctx: Context<CommitBid>, commitment: [u8; 32]) -> Result<()> {
+        let sale_round = &ctx.accounts.sale_round;
+        check_purchase_gates(
+            &ctx.accounts.launchpad,
+            &ctx.accounts.token_sale,
+            sale_round,
+            ctx.accounts.investor.key,
+            ctx.accounts.verification.is_some(),
+            &ctx.accounts.instructions,
+        )?;
+        require!(
+            sale_round.reveal_window > 0,
+            LaunchpadError::BiddingDisabled
+        );
+        require!(
+            Clock::get()?.unix_timestamp < sale_round.reveal_starts_at(),
+            LaunchpadError::BiddingClosed
+        );
+
+        let bid = &mut ctx.accounts.bid;
+        bid.sale_round = sale_round.key();
+        bid.investor = ctx.accounts.investor.key();
+        bid.commitment = commitment;
+        bid.revealed = false;
+        bid.bump = ctx.bumps.bid;
+        Ok(())
+    }
+
+    // Reveal a sealed bid during the reveal window, committing its SOL toward
+    // the round's pro-rata allocation
+    pub fn reveal_bid(ctx: Context<RevealBid>, amount: u64, nonce: [u8; 32]) -> Result<()> {
+        require!(amount > 0, LaunchpadError::ZeroAmount);
+        let sale_round = &mut ctx.accounts.sale_round;
+        check_purchase_gates(
+            &ctx.accounts.launchpad,
+            &ctx.accounts.token_sale,
+            sale_round,
+            ctx.accounts.investor.key,
+            ctx.accounts.verification.is_some(),
+            &ctx.accounts.instructions,
+        )?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= sale_round.reveal_starts_at(),
+            LaunchpadError::RevealNotOpen
+        );
+        require!(now < sale_round.end_time, LaunchpadError::RoundEnded);
+        sale_round.check_contribution(amount, ctx.accounts.commitment.amount == 0)?;
+
+        let bid = &mut ctx.accounts.bid;
+        require!(!bid.revealed, LaunchpadError::BidAlreadyRevealed);
+        let expected = hashv(&[
+            &amount.to_le_bytes(),
+            &nonce,
+            ctx.accounts.investor.key.as_ref(),
+        ]);
+        require!(
+            expected.to_bytes() == bid.commitment,
+            LaunchpadError::BidMismatch
+        );
+        bid.revealed = true;
+
+        ctx.accounts.commitment.add(
+            sale_round.key(),
+            ctx.accounts.investor.key(),
+            amount,
+            ctx.bumps.commitment,
+        )?;
+        sale_round.add_commitment(&mut ctx.accounts.token_sale, amount)?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.investor.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+
+        Ok(())
+    }
+
+    // Settle a commitment: fill it pro-rata against what the round can still
+    // take and refund the excess. Anyone may settle, so one absent committer
+    // can't hold up finalization
+    pub fn finalize_allocation(ctx: Context<FinalizeAllocation>) -> Result<()> {
+        let sale_round = &mut ctx.accounts.sale_round;
+        require!(
+            Clock::get()?.unix_timestamp >= sale_round.end_time,
+            LaunchpadError::RoundNotEnded
+        );
+
+        let commitment = &mut ctx.accounts.commitment;
+        require!(!commitment.settled, LaunchpadError::AlreadySettled);
+        commitment.settled = true;
+
+        // The first settlement fixes how much of the round's commitments fill,
+        // bounded by the sale's remaining capacity and the round's inventory,
+        // so everyone in the round is filled by the same fraction
+        let token_sale = &mut ctx.accounts.token_sale;
+        if sale_round.committed_settled == 0 {
+            let inventory = sale_round.cost_of(
+                sale_round.public_available(),
+                sale_round.price_per_token,
+                token_sale.token_decimals,
+            )?;
+            sale_round.commit_fillable = sale_round
+                .total_committed
+                .min(token_sale.remaining_capacity())
+                .min(inventory);
+            token_sale.committed_reserved = token_sale
+                .committed_reserved
+                .checked_add(sale_round.commit_fillable)
+                .ok_or(LaunchpadError::MathOverflow)?;
+        }
+        let share = sale_round.fill_share(commitment.amount)?;
+        token_sale.committed_reserved -= share;
+        sale_round.committed_settled = sale_round
+            .committed_settled
+            .checked_add(commitment.amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        token_sale.committed_settled = token_sale
+            .committed_settled
+            .checked_add(commitment.amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+
+        // Refund the whole commitment rather than strand it if the round's
+        // inventory has since run short
+        let (filled, tokens) = match sale_round.tokens_for(
+            share,
+            sale_round.price_per_token,
+            token_sale.token_decimals,
+            sale_round.public_available(),
+        ) {
+            Ok(tokens) if tokens > 0 => (share, tokens),
+            _ => (0, 0),
+        };
+        let excess = commitment.amount - filled;
+
+        token_sale.total_raised = token_sale
+            .total_raised
+            .checked_add(filled)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        sale_round.sol_raised = sale_round
+            .sol_raised
+            .checked_add(filled)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        sale_round.tokens_available -= tokens;
+        sale_round.tokens_sold += tokens;
+        token_sale.tokens_sold += tokens;
+
+        ctx.accounts.vesting.record(
+            token_sale.key(),
+            ctx.accounts.investor.key(),
+            tokens,
+            sale_round.price_per_token,
+            VestingSource::Purchase,
+            &token_sale.vesting_for(sale_round),
+            Clock::get()?.unix_timestamp,
+        );
+        ctx.accounts.vesting.stamp_address(
+            sale_round.key(),
+            ctx.accounts.contribution.vesting_count,
+            ctx.bumps.vesting,
+        );
+
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.amount = contribution
+            .amount
+            .checked_add(filled)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        contribution.record_spend(filled, tokens)?;
+        contribution.track(
+            token_sale.key(),
+            ctx.accounts.investor.key(),
+            ctx.bumps.contribution,
+        )?;
+
+        if excess > 0 {
+            let token_sale_key = token_sale.key();
+            let seeds = &[
+                VAULT_SEED,
+                token_sale_key.as_ref(),
+                &[token_sale.vault_bump],
+            ];
+            let signer = &[&seeds[..]];
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.investor.to_account_info(),
+                },
+                signer,
+            );
+            anchor_lang::system_program::transfer(cpi_context, excess)?;
+        }
+
+        Ok(())
+    }
+
+    // Settle the sale as succeeded or failed once it has ended
+    pub fn finalize_sale(ctx: Context<FinalizeSale>) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+
+        require!(
+            token_sale.status == SaleStatus::Active,
+            LaunchpadError::SaleNotActive
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let finalizable_at = token_sale
+            .end_time
+            .checked_add(token_sale.finalization_grace)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        require!(now >= finalizable_at, LaunchpadError::FinalizationTooEarly);
+        require!(
+            token_sale.committed_settled == token_sale.total_committed,
+            LaunchpadError::AllocationsPending
+        );
+
+        // Short of its targets, the first finalization buys the sale more time
+        if !token_sale.targets_met()
+            && token_sale.softcap_extension > 0
+            && !token_sale.softcap_extended
+        {
+            token_sale.end_time = now
+                .checked_add(token_sale.softcap_extension)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            token_sale.softcap_extended = true;
+            emit!(SaleExtended {
+                sale: token_sale.key(),
+                end_time: token_sale.end_time,
+            });
+            return Ok(());
+        }
+
+        token_sale.finalize(now);
+        emit!(token_sale.finalized_event(token_sale.key()));
+
+        Ok(())
+    }
+
+    // Refund an investor's SOL contribution from a failed sale
+    pub fn refund(ctx: Context<Refund>) -> Result<()> {
+        require!(
+            ctx.accounts.token_sale.status == SaleStatus::Failed,
+            LaunchpadError::SaleNotFailed
+        );
+        ctx.accounts
+            .token_sale
+            .check_refund_window(Clock::get()?.unix_timestamp)?;
+
+        // Mark before moving funds; the account is also closed on exit
+        let contribution = &mut ctx.accounts.contribution;
+        require!(!contribution.refunded, LaunchpadError::AlreadyRefunded);
+        contribution.refunded = true;
+        let amount = contribution.amount;
+
+        // total_raised tracks what the vault still holds for the sale
+        let token_sale = &mut ctx.accounts.token_sale;
+        token_sale.total_refunded = token_sale
+            .total_refunded
+            .checked_add(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        token_sale.total_raised = token_sale
+            .total_raised
+            .checked_sub(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+
+        let token_sale_key = ctx.accounts.token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[ctx.accounts.token_sale.vault_bump],
+        ];
+        pay_refund(
+            &ctx.accounts.vault,
+            &ctx.accounts.investor,
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program,
+            &[&seeds[..]],
+            amount,
+            ctx.accounts.launchpad.refund_fee_bps,
+        )
+    }
+
+    // Refund from a succeeded sale whose registrant never deposited enough tokens;
+    // the first such refund fails the sale so nobody can claim unbacked tokens
+    pub fn claim_failed_deposit_refund(ctx: Context<Refund>) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+        if token_sale.status == SaleStatus::Succeeded {
+            require!(
+                token_sale.deposit_deadline > 0
+                    && Clock::get()?.unix_timestamp
+                        >= token_sale
+                            .finalized_at
+                            .saturating_add(token_sale.deposit_deadline),
+                LaunchpadError::DepositDeadlineNotReached
+            );
+            require!(
+                token_sale.tokens_deposited < token_sale.tokens_owed(),
+                LaunchpadError::SaleFullyDeposited
+            );
+            // The refund window runs from the moment the sale failed
+            token_sale.status = SaleStatus::Failed;
+            token_sale.finalized_at = Clock::get()?.unix_timestamp;
+        }
+
+        refund(ctx)
+    }
+
+    // Tokens (in base units) that 1 SOL buys in an active round, for display
+    pub fn display_rate(ctx: Context<DisplayRate>) -> Result<u64> {
+        let sale_round = &ctx.accounts.sale_round;
+        require!(sale_round.is_active, LaunchpadError::RoundNotActive);
+        sale_round.tokens_per_sol(ctx.accounts.token_sale.token_decimals)
+    }
+
+    // An investor's blended SOL cost per whole token across rounds
+    pub fn avg_price(ctx: Context<AvgPrice>) -> Result<u64> {
+        ctx.accounts
+            .contribution
+            .avg_price(ctx.accounts.token_sale.token_decimals)
+    }
+
+    // SOL still acceptable before the hard cap is reached
+    pub fn remaining_capacity(ctx: Context<RemainingCapacity>) -> Result<u64> {
+        Ok(ctx.accounts.token_sale.remaining_capacity())
+    }
+
+    // Sum vested and still-locked tokens at the current time across the
+    // vesting schedules passed as remaining accounts
+    pub fn vesting_stats<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetVestingStats<'info>>,
+    ) -> Result<VestingStats> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_BATCH,
+            LaunchpadError::BatchTooLarge
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut stats = VestingStats {
+            vested: 0,
+            locked: 0,
+        };
+        for info in ctx.remaining_accounts {
+            let vesting = Account::<VestingSchedule>::try_from(info)?;
+            require_keys_eq!(
+                vesting.token_sale,
+                ctx.accounts.token_sale.key(),
+                LaunchpadError::ScheduleNotInSale
+            );
+            let vested = vesting.vested_at(now)?.min(vesting.total_allocation);
+            stats.vested = stats
+                .vested
+                .checked_add(vested)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            stats.locked = stats
+                .locked
+                .checked_add(vesting.total_allocation - vested)
+                .ok_or(LaunchpadError::MathOverflow)?;
+        }
+        Ok(stats)
+    }
+
+    // Summarize an investor's schedules across sales, passed as remaining accounts
+    pub fn investor_positions<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetInvestorPositions<'info>>,
+    ) -> Result<Vec<PositionSummary>> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_POSITIONS,
+            LaunchpadError::BatchTooLarge
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut positions = Vec::with_capacity(ctx.remaining_accounts.len());
+        for info in ctx.remaining_accounts {
+            let vesting = Account::<VestingSchedule>::try_from(info)?;
+            require_keys_eq!(
+                vesting.investor,
+                ctx.accounts.investor.key(),
+                LaunchpadError::ScheduleNotOwned
+            );
+            let claimable_now = vesting
+                .claimable_vested_at(vesting.monotonic_time(now))?
+                .min(vesting.total_allocation)
+                .saturating_sub(vesting.released);
+            positions.push(PositionSummary {
+                sale: vesting.token_sale,
+                total_allocation: vesting.total_allocation,
+                released: vesting.released,
+                claimable_now,
+            });
+        }
+        Ok(positions)
+    }
+
+    // Recompute the sale's accounting invariants. Remaining accounts are every
+    // round in `round_keys` order, followed by any vesting schedules to check.
+    pub fn audit_sale<'info>(ctx: Context<'_, '_, 'info, 'info, AuditSale<'info>>) -> Result<bool> {
+        let token_sale = &ctx.accounts.token_sale;
+        let round_count = token_sale.round_keys.len();
+        require!(
+            ctx.remaining_accounts.len() >= round_count,
+            LaunchpadError::InvalidAuditAccounts
+        );
+        let (rounds, vestings) = ctx.remaining_accounts.split_at(round_count);
+        require!(vestings.len() <= MAX_BATCH, LaunchpadError::BatchTooLarge);
+
+        // Closed rounds are no longer passed, so start from their totals.
+        // Rounds keep what they raised, the sale nets out refunds
+        let mut sol_raised = token_sale.closed_rounds_raised as u128;
+        let mut tokens_sold = token_sale.closed_rounds_sold as u128;
+        for (info, key) in rounds.iter().zip(token_sale.round_keys.iter()) {
+            require_keys_eq!(info.key(), *key, LaunchpadError::InvalidAuditAccounts);
+            let sale_round = Account::<SaleRound>::try_from(info)?;
+            sol_raised += sale_round.sol_raised as u128;
+            tokens_sold += sale_round.tokens_sold as u128;
+        }
+
+        // Grants come out of round inventory too, so the vault backs both
+        let tokens_owed = tokens_sold + token_sale.tokens_granted as u128;
+        let mut healthy = token_sale.total_raised as u128 + token_sale.total_refunded as u128
+            == sol_raised
+            && token_sale.tokens_sold as u128 == tokens_sold
+            && token_sale.tokens_deposited as u128 >= tokens_owed
+            && token_sale.tokens_claimed as u128 <= tokens_owed;
+        if !healthy {
+            msg!(
+                "raised {} vs rounds {}, sold {} vs rounds {}, granted {}, deposited {}, claimed {}",
+                token_sale.total_raised,
+                sol_raised,
+                token_sale.tokens_sold,
+                tokens_sold,
+                token_sale.tokens_granted,
+                token_sale.tokens_deposited,
+                token_sale.tokens_claimed
+            );
+        }
+
+        for info in vestings {
+            let vesting = Account::<VestingSchedule>::try_from(info)?;
+            require_keys_eq!(
+                vesting.token_sale,
+                token_sale.key(),
+                LaunchpadError::InvalidAuditAccounts
+            );
+            if vesting.released > vesting.total_allocation {
+                msg!("vesting {} released past its allocation", info.key());
+                healthy = false;
+            }
+        }
+
+        Ok(healthy)
+    }
+
+    // Claim vested tokens, or only up to `amount` of them
+    pub fn claim_tokens(ctx: Context<ClaimTokens>, amount: Option<u64>) -> Result<()> {
+        // Investors in a failed sale are refunded instead
+        require!(
+            ctx.accounts.token_sale.status != SaleStatus::Failed,
+            LaunchpadError::SaleFailed
+        );
+        if ctx.accounts.token_sale.claims_enabled_after_finalize {
+            require!(
+                ctx.accounts.token_sale.status != SaleStatus::Active,
+                LaunchpadError::ClaimsNotEnabled
+            );
+        }
+        // Under a deposit deadline a short sale can still fail and refund in
+        // full, so nothing is claimable until it has succeeded fully backed
+        let token_sale = &ctx.accounts.token_sale;
+        if token_sale.deposit_deadline > 0 {
+            require!(
+                token_sale.status == SaleStatus::Succeeded,
+                LaunchpadError::ClaimsNotEnabled
+            );
+            require!(
+                token_sale.tokens_deposited >= token_sale.tokens_owed(),
+                LaunchpadError::VaultUnderfunded
+            );
+        }
+
+        let vesting = &mut ctx.accounts.vesting;
+
+        let current_time = vesting.monotonic_time(Clock::get()?.unix_timestamp);
+
+        let claim_cooldown = ctx.accounts.launchpad.claim_cooldown;
+        if claim_cooldown > 0 && vesting.last_claim_time > 0 {
+            require!(
+                current_time >= vesting.last_claim_time.saturating_add(claim_cooldown),
+                LaunchpadError::ClaimCooldownActive
+            );
+        }
+
+        require!(
+            vesting.released < vesting.total_allocation,
+            LaunchpadError::NothingToClaim
+        );
+
+        // Clamp so cumulative releases can never exceed the allocation
+        let mut vested_amount = vesting
+            .claimable_vested_at(current_time)?
+            .min(vesting.total_allocation)
+            .saturating_sub(vesting.released);
+        // The rest stays claimable in later transactions
+        let max_claim_per_tx = ctx.accounts.token_sale.max_claim_per_tx;
+        if max_claim_per_tx > 0 {
+            vested_amount = vested_amount.min(max_claim_per_tx);
+        }
+        // Investors may take less than what's claimable; none or 0 takes it all
+        if let Some(amount) = amount.filter(|&amount| amount > 0) {
+            vested_amount = vested_amount.min(amount);
+        }
+
+        require!(vested_amount > 0, LaunchpadError::NothingToClaim);
+        require!(
+            ctx.accounts.vault_token_account.amount >= vested_amount,
+            LaunchpadError::VaultUnderfunded
+        );
+
+        // Backstop against accounting bugs: across all investors, claims can
+        // never pay out more than was deposited, whatever else the vault holds
+        let token_sale = &mut ctx.accounts.token_sale;
+        let tokens_claimed = token_sale
+            .tokens_claimed
+            .checked_add(vested_amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        if tokens_claimed > token_sale.tokens_deposited {
+            msg!(
+                "Claims would total {} tokens, {} deposited",
+                tokens_claimed,
+                token_sale.tokens_deposited
+            );
+            return err!(LaunchpadError::ClaimsExceedDeposits);
+        }
+        token_sale.tokens_claimed = tokens_claimed;
+
+        // Claim-and-stake routes the tokens to the escrow's ATA, and a recipient
+        // override to another wallet, unless the sale only pays investors directly
+        let escrow_account = &ctx.accounts.stake_escrow_token_account;
+        let recipient_account = &ctx.accounts.recipient_token_account;
+        if ctx.accounts.token_sale.claim_to_self_only {
+            require!(
+                escrow_account.is_none() && recipient_account.is_none(),
+                LaunchpadError::ClaimToSelfOnly
+            );
+        }
+        let destination = match (escrow_account, recipient_account) {
+            (Some(escrow_account), None) => escrow_account,
+            (None, Some(recipient_account)) => recipient_account,
+            (Some(_), Some(_)) => return err!(LaunchpadError::InvalidClaimDestination),
+            (None, None) => {
+                require!(
+                    ctx.accounts.stake_escrow.is_none(),
+                    LaunchpadError::InvalidStakeEscrow
+                );
+                &ctx.accounts.investor_token_account
+            }
+        };
+        // Leave the schedule untouched rather than fail inside the transfer
+        require!(!destination.is_frozen(), LaunchpadError::DestinationFrozen);
+
+        // Transfer tokens
+        let token_sale_key = ctx.accounts.token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[ctx.accounts.token_sale.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_token_account.to_account_info(),
+                to: destination.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            },
+            signer,
+        );
+
+        token::transfer(transfer_ctx, vested_amount)?;
+
+        vesting.released = vesting
+            .released
+            .checked_add(vested_amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        vesting.last_claim_time = current_time;
+
+        emit!(TokensClaimed {
+            vesting: vesting.key(),
+            investor: vesting.investor,
+            amount: vested_amount,
+            price_per_token: vesting.price_per_token,
+        });
+        if let Some(stake_escrow) = &ctx.accounts.stake_escrow {
+            emit!(ClaimedToStake {
+                vesting: vesting.key(),
+                investor: vesting.investor,
+                stake_escrow: stake_escrow.key(),
+                amount: vested_amount,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Move staked tokens out of the investor's escrow back to their wallet
+    pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
+        require!(amount > 0, LaunchpadError::ZeroAmount);
+
+        let investor_key = ctx.accounts.investor.key();
+        let seeds = &[STAKE_SEED, investor_key.as_ref(), &[ctx.bumps.stake_escrow]];
+        let signer = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.stake_escrow_token_account.to_account_info(),
+                to: ctx.accounts.investor_token_account.to_account_info(),
+                authority: ctx.accounts.stake_escrow.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        emit!(Unstaked {
+            investor: investor_key,
+            stake_escrow: ctx.accounts.stake_escrow.key(),
+            mint: ctx.accounts.token_mint.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Withdraw the SOL raised by a successful sale to the registrant
+    pub fn withdraw_raised(ctx: Context<WithdrawRaised>) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+
+        require!(
+            token_sale.status == SaleStatus::Succeeded,
+            LaunchpadError::SaleNotSucceeded
+        );
+        require!(
+            token_sale.tokens_deposited >= token_sale.tokens_owed(),
+            LaunchpadError::VaultUnderfunded
+        );
+        require!(
+            token_sale.withdraw_stream_duration == 0,
+            LaunchpadError::WithdrawalStreamed
+        );
+        let vault_raised = token_sale.vault_raised();
+        let amount = vault_raised.saturating_sub(token_sale.withdrawn);
+        require!(amount > 0, LaunchpadError::NothingToWithdraw);
+        token_sale.withdrawn = vault_raised;
+
+        let token_sale_key = token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[token_sale.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.vault.to_account_info(),
+                to: ctx.accounts.payee()?,
+            },
+            signer,
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+
+        Ok(())
+    }
+
+    // Withdraw the portion of raised SOL streamed so far
+    pub fn withdraw_streamed(ctx: Context<WithdrawRaised>) -> Result<()> {
+        let token_sale = &mut ctx.accounts.token_sale;
+
+        require!(
+            token_sale.status == SaleStatus::Succeeded,
+            LaunchpadError::SaleNotSucceeded
+        );
+        require!(
+            token_sale.tokens_deposited >= token_sale.tokens_owed(),
+            LaunchpadError::VaultUnderfunded
+        );
+        let streamed = token_sale.streamed_at(Clock::get()?.unix_timestamp);
+        let amount = streamed.saturating_sub(token_sale.withdrawn);
+        require!(amount > 0, LaunchpadError::NothingToWithdraw);
+        token_sale.withdrawn = streamed;
+
+        let token_sale_key = token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[token_sale.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.vault.to_account_info(),
+                to: ctx.accounts.payee()?,
+            },
+            signer,
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+
+        Ok(())
+    }
+
+    // Refund a purchase from an isolated round that failed, out of the
+    // round's own vault
+    pub fn refund_round_purchase(ctx: Context<RefundRoundPurchase>) -> Result<()> {
+        let vesting = &ctx.accounts.vesting;
+        let sale_round = &mut ctx.accounts.sale_round;
+        let token_sale = &mut ctx.accounts.token_sale;
+
+        require!(sale_round.isolated, LaunchpadError::RoundNotIsolated);
+        require!(
+            sale_round.tranche_failed(token_sale.status, Clock::get()?.unix_timestamp),
+            LaunchpadError::RoundNotFailed
+        );
+        require!(
+            vesting.paid > 0 && vesting.released == 0,
+            LaunchpadError::NotCancellable
+        );
+
+        // Return the allocation to the round; the payment was never in the sale vault
+        sale_round.tokens_available += vesting.total_allocation;
+        sale_round.tokens_sold -= vesting.total_allocation;
+        token_sale.tokens_sold -= vesting.total_allocation;
+        sale_round.sol_raised = sale_round
+            .sol_raised
+            .checked_sub(vesting.paid)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        token_sale.total_raised = token_sale
+            .total_raised
+            .checked_sub(vesting.paid)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        token_sale.isolated_raised = token_sale
+            .isolated_raised
+            .checked_sub(vesting.paid)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.tokens_bought -= vesting.total_allocation;
+        contribution.total_spent -= vesting.paid;
+        contribution.total_tokens -= vesting.total_allocation;
+
+        let sale_round_key = sale_round.key();
+        let seeds = &[
+            ROUND_VAULT_SEED,
+            sale_round_key.as_ref(),
+            &[sale_round.vault_bump],
+        ];
+        pay_refund(
+            &ctx.accounts.round_vault,
+            &ctx.accounts.investor,
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program,
+            &[&seeds[..]],
+            vesting.paid,
+            ctx.accounts.launchpad.refund_fee_bps,
+        )
+    }
+
+    // Withdraw what an isolated round raised once it has met its soft cap
+    pub fn withdraw_round(ctx: Context<WithdrawRound>) -> Result<()> {
+        let token_sale = &ctx.accounts.token_sale;
+        let sale_round = &mut ctx.accounts.sale_round;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            token_sale.status == SaleStatus::Succeeded,
+            LaunchpadError::SaleNotSucceeded
+        );
+        require!(
+            token_sale.tokens_deposited >= token_sale.tokens_owed(),
+            LaunchpadError::VaultUnderfunded
+        );
+        require!(sale_round.isolated, LaunchpadError::RoundNotIsolated);
+        require!(now >= sale_round.end_time, LaunchpadError::RoundNotEnded);
+        require!(
+            !sale_round.tranche_failed(token_sale.status, now),
+            LaunchpadError::RoundNotSucceeded
+        );
+        require!(
+            !sale_round.vault_withdrawn && sale_round.sol_raised > 0,
+            LaunchpadError::NothingToWithdraw
+        );
+        sale_round.vault_withdrawn = true;
+        let amount = sale_round.sol_raised;
+
+        let sale_round_key = sale_round.key();
+        let seeds = &[
+            ROUND_VAULT_SEED,
+            sale_round_key.as_ref(),
+            &[ctx.accounts.sale_round.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.round_vault.to_account_info(),
+                to: ctx.accounts.payee()?,
+            },
+            signer,
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)
+    }
+
+    // Lengthen a schedule's vesting; released tokens are kept and further
+    // claims wait until the longer stream catches up
+    pub fn extend_vesting(ctx: Context<ExtendVesting>, new_duration: u64) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting;
+        require!(
+            new_duration > vesting.duration,
+            LaunchpadError::VestingNotExtended
+        );
+        ctx.accounts
+            .launchpad
+            .check_vesting_duration(new_duration)?;
+        vesting.duration = new_duration;
+        Ok(())
+    }
+
+    // Hand the unreleased remainder of a schedule to a new beneficiary
+    pub fn transfer_vesting(ctx: Context<TransferVesting>, new_beneficiary: Pubkey) -> Result<()> {
+        require!(
+            ctx.accounts.token_sale.transferable,
+            LaunchpadError::VestingNotTransferable
+        );
+        require!(
+            new_beneficiary != Pubkey::default(),
+            LaunchpadError::InvalidBeneficiary
+        );
+
+        // The payment stays with the original contribution, so it can no
+        // longer be cancelled
+        let vesting = &mut ctx.accounts.vesting;
+        vesting.investor = new_beneficiary;
+        vesting.paid = 0;
+        Ok(())
+    }
+
+    // Sweep leftover lamports above rent exemption from a settled sale's vault
+    pub fn sweep_vault_dust(ctx: Context<SweepVaultDust>) -> Result<()> {
+        let authority = ctx.accounts.authority.key();
+        let token_sale = &ctx.accounts.token_sale;
+        require!(
+            authority == token_sale.registrant || authority == ctx.accounts.launchpad.admin,
+            LaunchpadError::Unauthorized
+        );
+        require!(token_sale.is_settled(), LaunchpadError::SaleNotSettled);
+
+        let rent_exempt = Rent::get()?.minimum_balance(0);
+        let dust = ctx.accounts.vault.lamports().saturating_sub(rent_exempt);
+        require!(dust > 0, LaunchpadError::NothingToWithdraw);
+
+        let token_sale_key = token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[token_sale.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.vault.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+            signer,
+        );
+        anchor_lang::system_program::transfer(cpi_context, dust)?;
+
+        Ok(())
+    }
+
+    // Take an ended round's unsold tokens off sale. They stay in the vault as
+    // general inventory, or go back to the registrant with `to_registrant`.
+    pub fn reclaim_unsold(ctx: Context<ReclaimUnsold>, to_registrant: bool) -> Result<()> {
+        let sale_round = &mut ctx.accounts.sale_round;
+        let token_sale = &mut ctx.accounts.token_sale;
+        // A soft-cap extension keeps the round selling past its own end time
+        require!(
+            Clock::get()?.unix_timestamp >= token_sale.round_end(sale_round),
+            LaunchpadError::RoundNotEnded
+        );
+        require!(!sale_round.is_active, LaunchpadError::RoundActive);
+        let unsold = sale_round.tokens_available;
+        require!(unsold > 0, LaunchpadError::NothingToWithdraw);
+
+        sale_round.tokens_available = 0;
+        sale_round.reserved_tokens = 0;
+
+        if to_registrant {
+            // Sold and granted tokens must stay backed by what remains deposited
+            let remaining = token_sale.tokens_deposited.saturating_sub(unsold);
+            require!(
+                remaining >= token_sale.tokens_owed(),
+                LaunchpadError::VaultUnderfunded
+            );
+            token_sale.tokens_deposited = remaining;
+
+            let token_sale_key = token_sale.key();
+            let seeds = &[
+                VAULT_SEED,
+                token_sale_key.as_ref(),
+                &[token_sale.vault_bump],
+            ];
+            let signer = &[&seeds[..]];
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: ctx.accounts.registrant_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer,
+            );
+            token::transfer(transfer_ctx, unsold)?;
+        }
+
+        emit!(UnsoldReclaimed {
+            round: sale_round.key(),
+            tokens: unsold,
+            to_registrant,
+        });
+        Ok(())
+    }
+
+    // Sweep and close a failed sale that investors abandoned long ago
+    pub fn reclaim_abandoned<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReclaimAbandoned<'info>>,
+    ) -> Result<()> {
+        let token_sale = &ctx.accounts.token_sale;
+
+        require!(
+            token_sale.status == SaleStatus::Failed,
+            LaunchpadError::SaleNotFailed
+        );
+        // A closed refund window means nobody can claim the funds anymore
+        let delay = if token_sale.refund_window > 0 {
+            token_sale.refund_window
+        } else {
+            ABANDONED_RECLAIM_DELAY
+        };
+        let reclaimable_at = token_sale
+            .finalized_at
+            .checked_add(delay)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        require!(
+            Clock::get()?.unix_timestamp >= reclaimable_at,
+            LaunchpadError::ReclaimTooEarly
+        );
+
+        // Every open round goes with the sale, passed in round_keys order, then
+        // the vault of each isolated one among them, then the vault's and the
+        // treasury's token accounts for each accepted payment mint
+        let round_count = token_sale.round_keys.len();
+        require!(
+            ctx.remaining_accounts.len() >= round_count,
+            LaunchpadError::RoundAccountsMismatch
+        );
+        let (rounds, rest) = ctx.remaining_accounts.split_at(round_count);
+        let mut isolated_rounds = Vec::new();
+        for (info, round_key) in rounds.iter().zip(&token_sale.round_keys) {
+            require_keys_eq!(
+                info.key(),
+                *round_key,
+                LaunchpadError::RoundAccountsMismatch
+            );
+            let sale_round = Account::<SaleRound>::try_from(info)?;
+            if sale_round.isolated {
+                isolated_rounds.push((*round_key, sale_round.vault_bump));
+            }
+            sale_round.close(ctx.accounts.treasury.to_account_info())?;
+        }
+
+        // Unrefunded SOL in isolated rounds' vaults goes to the treasury too
+        require!(
+            rest.len() >= isolated_rounds.len(),
+            LaunchpadError::RoundAccountsMismatch
+        );
+        let (round_vaults, payment_accounts) = rest.split_at(isolated_rounds.len());
+        let mut round_lamports: u64 = 0;
+        for (info, (round_key, vault_bump)) in round_vaults.iter().zip(&isolated_rounds) {
+            let seeds = &[ROUND_VAULT_SEED, round_key.as_ref(), &[*vault_bump]];
+            let round_vault = Pubkey::create_program_address(seeds, ctx.program_id)
+                .map_err(|_| error!(LaunchpadError::RoundAccountsMismatch))?;
+            require_keys_eq!(
+                info.key(),
+                round_vault,
+                LaunchpadError::RoundAccountsMismatch
+            );
+            let lamports = info.lamports();
+            if lamports > 0 {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: info.clone(),
+                            to: ctx.accounts.treasury.to_account_info(),
+                        },
+                        &[&seeds[..]],
+                    ),
+                    lamports,
+                )?;
+                round_lamports = round_lamports
+                    .checked_add(lamports)
+                    .ok_or(LaunchpadError::MathOverflow)?;
+            }
+        }
+
+        let token_sale_key = token_sale.key();
+        let seeds = &[
+            VAULT_SEED,
+            token_sale_key.as_ref(),
+            &[ctx.accounts.token_sale.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Unrefunded SPL payments are swept to the treasury's account for the
+        // mint, and the vault's account for it closed
+        require!(
+            payment_accounts.len() == 2 * token_sale.accepted_payments.len(),
+            LaunchpadError::PaymentAccountsMismatch
+        );
+        for (pair, payment) in payment_accounts
+            .chunks(2)
+            .zip(&token_sale.accepted_payments)
+        {
+            let (vault_info, treasury_info) = (&pair[0], &pair[1]);
+            require_keys_eq!(
+                vault_info.key(),
+                get_associated_token_address(&ctx.accounts.vault.key(), &payment.mint),
+                LaunchpadError::PaymentAccountsMismatch
+            );
+            // Nobody ever paid in this mint if the account was never created
+            if vault_info.data_is_empty() {
+                continue;
+            }
+            let vault_payment_account = Account::<TokenAccount>::try_from(vault_info)?;
+            if vault_payment_account.amount > 0 {
+                let treasury_payment_account = Account::<TokenAccount>::try_from(treasury_info)?;
+                require!(
+                    treasury_payment_account.mint == payment.mint
+                        && treasury_payment_account.owner == ctx.accounts.treasury.key(),
+                    LaunchpadError::PaymentAccountsMismatch
+                );
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: vault_info.clone(),
+                            to: treasury_info.clone(),
+                            authority: ctx.accounts.vault.to_account_info(),
+                        },
+                        signer,
+                    ),
+                    vault_payment_account.amount,
+                )?;
+            }
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault_info.clone(),
+                    destination: ctx.accounts.treasury.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer,
+            ))?;
+        }
+
+        // Unsold tokens go back to the registrant and the vault's token account closes
+        let tokens_returned = ctx.accounts.vault_token_account.amount;
+        if tokens_returned > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.registrant_token_account.to_account_info(),
+                        authority: ctx.accounts.vault.to_account_info(),
+                    },
+                    signer,
+                ),
+                tokens_returned,
+            )?;
+        }
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.vault_token_account.to_account_info(),
+                destination: ctx.accounts.treasury.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            },
+            signer,
+        ))?;
+
+        let lamports = ctx.accounts.vault.lamports();
+        if lamports > 0 {
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                },
+                signer,
+            );
+            anchor_lang::system_program::transfer(cpi_context, lamports)?;
+        }
+
+        emit!(AbandonedSaleReclaimed {
+            sale: token_sale.key(),
+            treasury: ctx.accounts.treasury.key(),
+            lamports,
+            round_lamports,
+            tokens_returned,
+        });
+
+        Ok(())
+    }
+}
+
+// Round times must be positive and within MAX_ROUND_HORIZON of now
+fn validate_round_times(start_time: i64, end_time: i64) -> Result<()> {
+    let latest = Clock::get()?
+        .unix_timestamp
+        .saturating_add(MAX_ROUND_HORIZON);
+    require!(
+        start_time > 0 && end_time > 0 && start_time <= latest && end_time <= latest,
+        LaunchpadError::InvalidTimestamp
+    );
+    Ok(())
+}
+
+// 10^decimals as u128, for converting between whole tokens and base units
+fn decimal_scale(decimals: u8) -> Result<u128> {
+    require!(
+        decimals <= MAX_TOKEN_DECIMALS,
+        LaunchpadError::UnsupportedDecimals
+    );
+    Ok(10u128.pow(decimals as u32))
+}
+
+// Fund the vault PDA up to the rent-exempt minimum for a data-less account
+fn fund_vault_rent<'info>(
+    payer: &Signer<'info>,
+    vault: &SystemAccount<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let shortfall = Rent::get()?
+        .minimum_balance(0)
+        .saturating_sub(vault.lamports());
+    if shortfall == 0 {
+        return Ok(());
+    }
+
+    let cpi_context = CpiContext::new(
+        system_program.to_account_info(),
+        anchor_lang::system_program::Transfer {
+            from: payer.to_account_info(),
+            to: vault.to_account_info(),
+        },
+    );
+    anchor_lang::system_program::transfer(cpi_context, shortfall)
+}
+
+// Pay a refund out of the vault, keeping `fee_bps` of it for the treasury
+fn pay_refund<'info>(
+    vault: &SystemAccount<'info>,
+    investor: &Signer<'info>,
+    treasury: &SystemAccount<'info>,
+    system_program: &Program<'info, System>,
+    signer: &[&[&[u8]]],
+    amount: u64,
+    fee_bps: u16,
+) -> Result<()> {
+    let fee = (amount as u128 * fee_bps as u128 / 10_000) as u64;
+    for (to, lamports) in [
+        (investor.to_account_info(), amount - fee),
+        (treasury.to_account_info(), fee),
+    ] {
+        if lamports == 0 {
+            continue;
+        }
+        let cpi_context = CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: vault.to_account_info(),
+                to,
+            },
+            signer,
+        );
+        anchor_lang::system_program::transfer(cpi_context, lamports)?;
+    }
+    Ok(())
+}
+
+// Check that the preceding instruction is an Ed25519 signature by `authority`
+// over `sale || investor || expires_at`, and that it hasn't expired
+// The gates on putting money into a round that don't depend on how it's
+// paid: the sale and round are open and the investor is admitted
+fn check_purchase_gates(
+    launchpad: &Launchpad,
+    token_sale: &Account<TokenSale>,
+    sale_round: &SaleRound,
+    investor: &Pubkey,
+    verified: bool,
+    instructions: &AccountInfo,
+) -> Result<()> {
+    require!(
+        token_sale.status == SaleStatus::Active,
+        LaunchpadError::SaleNotActive
+    );
+    require!(
+        token_sale.state == SaleState::Open,
+        LaunchpadError::SaleWindingDown
+    );
+    require!(sale_round.is_active, LaunchpadError::RoundNotActive);
+
+    if launchpad.block_self_purchase {
+        require!(
+            *investor != token_sale.registrant,
+            LaunchpadError::SelfPurchaseBlocked
+        );
+    }
+    if token_sale.require_verification {
+        require!(verified, LaunchpadError::InvestorNotVerified);
+    }
+    if token_sale.kyc_authority != Pubkey::default() {
+        verify_kyc_attestation(
+            instructions,
+            &token_sale.kyc_authority,
+            &token_sale.key(),
+            investor,
+        )?;
+    }
+    Ok(())
+}
+
+fn verify_kyc_attestation(
+    instructions: &AccountInfo,
+    authority: &Pubkey,
+    sale: &Pubkey,
+    investor: &Pubkey,
+) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)?;
+    require!(current_index > 0, LaunchpadError::KycRequired);
+    let ix = load_instruction_at_checked(current_index as usize - 1, instructions)?;
+    require!(
+        ix.program_id == ed25519_program::ID,
+        LaunchpadError::KycRequired
+    );
+
+    // Header: count, padding, then one set of offsets that must all point into this instruction
+    let data = &ix.data;
+    require!(
+        data.len() >= 16 && data[0] == 1,
+        LaunchpadError::KycRequired
+    );
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]) as usize;
+    let this_ix = u16::MAX as usize;
+    require!(
+        read_u16(4) == this_ix && read_u16(8) == this_ix && read_u16(14) == this_ix,
+        LaunchpadError::KycRequired
+    );
+
+    let pubkey_offset = read_u16(6);
+    let message_offset = read_u16(10);
+    let message_size = read_u16(12);
+    require!(
+        message_size == 32 + 32 + 8
+            && data.len() >= pubkey_offset + 32
+            && data.len() >= message_offset + message_size,
+        LaunchpadError::KycRequired
+    );
+    require!(
+        data[pubkey_offset..pubkey_offset + 32] == authority.to_bytes(),
+        LaunchpadError::KycRequired
+    );
+
+    let message = &data[message_offset..message_offset + message_size];
+    require!(
+        message[..32] == sale.to_bytes() && message[32..64] == investor.to_bytes(),
+        LaunchpadError::KycRequired
+    );
+    let expires_at = i64::from_le_bytes(message[64..72].try_into().unwrap());
+    require!(
+        Clock::get()?.unix_timestamp <= expires_at,
+        LaunchpadError::KycRequired
+    );
+
+    Ok(())
+}
+
+// Accounts and Error handling
+#[error_code]
+pub enum LaunchpadError {
+    #[msg("Contribution too low")]
     ContributionTooLow,
     #[msg("Contribution exceeded")]
     ContributionExceeded,
@@ -176,60 +3252,1403 @@ pub enum LaunchpadError {
     VestingNotStarted,
     #[msg("Nothing to claim")]
     NothingToClaim,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Round already has sales")]
+    RoundHasSales,
+    #[msg("Round is active")]
+    RoundActive,
+    #[msg("Sale is not active")]
+    SaleNotActive,
+    #[msg("Finalization too early")]
+    FinalizationTooEarly,
+    #[msg("Valid KYC attestation required")]
+    KycRequired,
+    #[msg("Amount does not match an allowed tier")]
+    InvalidTier,
+    #[msg("Sale has not failed")]
+    SaleNotFailed,
+    #[msg("Reclaim window has not opened")]
+    ReclaimTooEarly,
+    #[msg("Too many accepted payments")]
+    TooManyPayments,
+    #[msg("Invalid price")]
+    InvalidPrice,
+    #[msg("Payment mint not accepted")]
+    UnsupportedPaymentMint,
+    #[msg("Payment token accounts missing")]
+    PaymentAccountsMissing,
+    #[msg("Invalid vesting terms")]
+    InvalidVestingTerms,
+    #[msg("Contribution already refunded")]
+    AlreadyRefunded,
+    #[msg("Sale failed")]
+    SaleFailed,
+    #[msg("Amount must be greater than zero")]
+    ZeroAmount,
+    #[msg("Sale has not succeeded")]
+    SaleNotSucceeded,
+    #[msg("Nothing to withdraw")]
+    NothingToWithdraw,
+    #[msg("Invalid lead time")]
+    InvalidLeadTime,
+    #[msg("Round starts too soon")]
+    InsufficientLeadTime,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Sale is not settled")]
+    SaleNotSettled,
+    #[msg("Round is not active")]
+    RoundNotActive,
+    #[msg("Round has ended")]
+    RoundEnded,
+    #[msg("Round has not started")]
+    RoundNotStarted,
+    #[msg("Round has not ended")]
+    RoundNotEnded,
+    #[msg("Commitment already settled")]
+    AlreadySettled,
+    #[msg("Commitments are still awaiting allocation")]
+    AllocationsPending,
+    #[msg("Mint is not allowed on this launchpad")]
+    MintNotAllowed,
+    #[msg("Invalid cooldown")]
+    InvalidCooldown,
+    #[msg("Claim cooldown active")]
+    ClaimCooldownActive,
+    #[msg("Registrants cannot buy their own sale")]
+    SelfPurchaseBlocked,
+    #[msg("Invalid fee configuration")]
+    InvalidFeeConfig,
+    #[msg("Sale vault holds too few tokens; the registrant must deposit more")]
+    VaultUnderfunded,
+    #[msg("Invalid withdraw stream duration")]
+    InvalidStreamDuration,
+    #[msg("Raised funds are streamed; use withdraw_streamed")]
+    WithdrawalStreamed,
+    #[msg("Vault holds too few deposited tokens for this round")]
+    InsufficientVaultInventory,
+    #[msg("Invalid cooling-off period")]
+    InvalidCooloff,
+    #[msg("Cooling-off window has passed")]
+    CooloffExpired,
+    #[msg("This vesting schedule can't be cancelled")]
+    NotCancellable,
+    #[msg("Purchase exceeds the per-transaction token limit")]
+    TxTokenLimitExceeded,
+    #[msg("Launchpad investor cap reached")]
+    InvestorCapReached,
+    #[msg("Destination token account is frozen")]
+    DestinationFrozen,
+    #[msg("Price is below the launchpad floor")]
+    PriceBelowFloor,
+    #[msg("Sale has the maximum number of rounds")]
+    TooManyRounds,
+    #[msg("Vesting schedules in this sale are not transferable")]
+    VestingNotTransferable,
+    #[msg("Invalid beneficiary")]
+    InvalidBeneficiary,
+    #[msg("Round time is out of range")]
+    InvalidTimestamp,
+    #[msg("Vesting can only be extended")]
+    VestingNotExtended,
+    #[msg("Invalid deposit deadline")]
+    InvalidDepositDeadline,
+    #[msg("Deposit deadline has not passed")]
+    DepositDeadlineNotReached,
+    #[msg("Every sold token has been deposited")]
+    SaleFullyDeposited,
+    #[msg("Round is sold out")]
+    RoundSoldOut,
+    #[msg("Contribution exceeds the remaining hard cap")]
+    HardCapExceeded,
+    #[msg("Claims open once the sale is finalized")]
+    ClaimsNotEnabled,
+    #[msg("Token hard cap reached")]
+    TokenHardCapReached,
+    #[msg("Invalid reveal window")]
+    InvalidRevealWindow,
+    #[msg("Sealed bidding is not enabled for this round")]
+    BiddingDisabled,
+    #[msg("Bidding has closed")]
+    BiddingClosed,
+    #[msg("Reveal window has not opened")]
+    RevealNotOpen,
+    #[msg("Bid already revealed")]
+    BidAlreadyRevealed,
+    #[msg("Revealed bid does not match its commitment")]
+    BidMismatch,
+    #[msg("Invalid round extension")]
+    InvalidExtension,
+    #[msg("Round extension limit reached")]
+    ExtensionLimitReached,
+    #[msg("Stake escrow passed without its token account")]
+    InvalidStakeEscrow,
+    #[msg("Sale token mint is invalid")]
+    InvalidMint,
+    #[msg("Audit accounts do not match the sale")]
+    InvalidAuditAccounts,
+    #[msg("Round accounts do not match the configs")]
+    InvalidRoundAccounts,
+    #[msg("Soft cap extension must not be negative")]
+    InvalidSoftcapExtension,
+    #[msg("Too many accounts in one batch")]
+    BatchTooLarge,
+    #[msg("Reserve exceeds the round's available tokens")]
+    InvalidReserve,
+    #[msg("Investor has not been verified")]
+    InvestorNotVerified,
+    #[msg("Points account required for this sale")]
+    PointsAccountRequired,
+    #[msg("Points account does not belong to the investor or sale")]
+    InvalidPointsAccount,
+    #[msg("Purchase exceeds the investor's points-based cap")]
+    PointsCapExceeded,
+    #[msg("Sale has not been finalized")]
+    SaleNotFinalized,
+    #[msg("Signer is not the pending registrant")]
+    NotPendingRegistrant,
+    #[msg("Claims may only go to the investor's own account")]
+    ClaimToSelfOnly,
+    #[msg("Pass either a stake escrow or a recipient, not both")]
+    InvalidClaimDestination,
+    #[msg("Vesting schedule belongs to another sale")]
+    ScheduleNotInSale,
+    #[msg("The vault's wSOL account is required and must be owned by the vault")]
+    WsolAccountRequired,
+    #[msg("Sale already has contributions")]
+    SaleHasContributions,
+    #[msg("Fee increases must be queued behind the timelock")]
+    FeeIncreaseTimelocked,
+    #[msg("Timelock delay has not elapsed")]
+    TimelockNotElapsed,
+    #[msg("Timelock delay must not be negative")]
+    InvalidTimelockDelay,
+    #[msg("Refunded investors may not purchase again in this sale")]
+    ReentryBlocked,
+    #[msg("Vesting schedule belongs to another investor")]
+    ScheduleNotOwned,
+    #[msg("Terms hash does not match the sale's registered terms")]
+    TermsHashMismatch,
+    #[msg("Vesting duration exceeds the launchpad maximum")]
+    VestingTooLong,
+    #[msg("Sale is already finalized")]
+    SaleFinalized,
+    #[msg("Allocation cap must be at most 10000 bps")]
+    InvalidAllocationCap,
+    #[msg("Round position account required")]
+    RoundPositionRequired,
+    #[msg("Purchase exceeds the investor's share of the round")]
+    AllocationCapExceeded,
+    #[msg("Mint decimals above the supported maximum")]
+    UnsupportedDecimals,
+    #[msg("Missing or invalid custody account")]
+    InvalidCustody,
+    #[msg("Vesting cannot start before the sale was registered")]
+    InvalidVestingStart,
+    #[msg("Sale is winding down and takes no new purchases")]
+    SaleWindingDown,
+    #[msg("Sale has not been approved by the launchpad admin")]
+    SaleNotApproved,
+    #[msg("Claims would exceed the tokens deposited for the sale")]
+    ClaimsExceedDeposits,
+    #[msg("Refund window is negative or below the launchpad minimum")]
+    InvalidRefundWindow,
+    #[msg("The refund window for this sale has closed")]
+    RefundWindowClosed,
+    #[msg("Round already has purchases")]
+    RoundHasPurchases,
+    #[msg("Purchases in this round must pay into its own vault")]
+    RoundVaultRequired,
+    #[msg("Round doesn't have its own vault")]
+    RoundNotIsolated,
+    #[msg("Round has not failed")]
+    RoundNotFailed,
+    #[msg("Round did not meet its soft cap")]
+    RoundNotSucceeded,
+    #[msg("Topping up active rounds is not allowed for this sale")]
+    ActiveTopUpBlocked,
+    #[msg("Another round is already active for this sale")]
+    RoundOverlap,
+    #[msg("ATA sponsor doesn't match the sale's ata_payer policy")]
+    InvalidAtaSponsor,
+    #[msg("Velocity window must be positive when a velocity limit is set")]
+    InvalidVelocityWindow,
+    #[msg("Shortening the timelock delay must be queued")]
+    TimelockDelayShortened,
+    #[msg("Paying with an SPL token needs the investor's payment position for that mint")]
+    PaymentPositionRequired,
+    #[msg("Isolated round still holds funds in its vault")]
+    RoundVaultNotEmpty,
+    #[msg("Pass every open round of the sale in order, then its isolated rounds' vaults")]
+    RoundAccountsMismatch,
+    #[msg("Changing the treasury must be queued while a timelock is set")]
+    TreasuryChangeTimelocked,
+    #[msg("Pass the vault's and the treasury's token accounts for each accepted payment mint")]
+    PaymentAccountsMismatch,
+}
+
+// Events
+#[event]
+pub struct AbandonedSaleReclaimed {
+    pub sale: Pubkey,
+    pub treasury: Pubkey,
+    pub lamports: u64,
+    // Swept from isolated rounds' own vaults
+    pub round_lamports: u64,
+    pub tokens_returned: u64,
+}
+
+#[event]
+pub struct FeeConfigUpdated {
+    pub launchpad: Pubkey,
+    pub old_fee_bps: u16,
+    pub old_treasury: Pubkey,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+}
+
+// Any other admin setting change; booleans are reported as 0 or 1
+#[event]
+pub struct LaunchpadSettingChanged {
+    pub launchpad: Pubkey,
+    pub setting: LaunchpadSetting,
+    pub old_value: u64,
+    pub new_value: u64,
+}
+
+#[event]
+pub struct SaleApproved {
+    pub launchpad: Pubkey,
+    pub sale: Pubkey,
+}
+
+#[event]
+pub struct UnsoldReclaimed {
+    pub round: Pubkey,
+    pub tokens: u64,
+    pub to_registrant: bool,
+}
+
+#[event]
+pub struct VelocityBreakerTripped {
+    pub sale: Pubkey,
+    pub window_raised: u64,
+    pub velocity_limit: u64,
+}
+
+#[event]
+pub struct RoundActivated {
+    pub round: Pubkey,
+    pub tokens_available: u64,
+    pub price: u64,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[event]
+pub struct ActionQueued {
+    pub launchpad: Pubkey,
+    pub action: AdminAction,
+    pub execute_after: i64,
+}
+
+#[event]
+pub struct ActionCancelled {
+    pub launchpad: Pubkey,
+    pub action: AdminAction,
+}
+
+#[event]
+pub struct AdminChanged {
+    pub launchpad: Pubkey,
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+// A sale wound down (paused) or reopened by its registrant
+#[event]
+pub struct PauseToggled {
+    pub sale: Pubkey,
+    pub paused: bool,
+}
+
+#[event]
+pub struct WhitelistUpdated {
+    pub launchpad: Pubkey,
+    pub mint: Pubkey,
+    pub allowed: bool,
+}
+
+#[event]
+pub struct PartialFill {
+    pub sale_round: Pubkey,
+    pub investor: Pubkey,
+    pub requested: u64,
+    pub filled: u64,
+    pub tokens: u64,
+}
+
+#[event]
+pub struct SaleFinalized {
+    pub sale: Pubkey,
+    pub status: SaleStatus,
+    pub total_raised: u64,
+    pub soft_cap: u64,
+    pub withdrawals_enabled: bool,
+    pub refunds_enabled: bool,
+}
+
+#[event]
+pub struct TokensClaimed {
+    pub vesting: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub price_per_token: u64,
+}
+
+#[event]
+pub struct RegistrantTransferred {
+    pub sale: Pubkey,
+    pub previous: Pubkey,
+    pub registrant: Pubkey,
+}
+
+#[event]
+pub struct TokensPurchased {
+    pub sale_round: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub tokens: u64,
+    // The price actually charged, after any payment mint or investor override
+    pub price_per_token: u64,
+    pub payment_mint: Option<Pubkey>,
+}
+
+#[event]
+pub struct SaleExtended {
+    pub sale: Pubkey,
+    pub end_time: i64,
+}
+
+#[event]
+pub struct ClaimedToStake {
+    pub vesting: Pubkey,
+    pub investor: Pubkey,
+    pub stake_escrow: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct Unstaked {
+    pub investor: Pubkey,
+    pub stake_escrow: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = admin, space = 8 + 32 + 8 + 32 + 8 + 1 + 8 + 1 + 2 + 8 + 8 + 8 + 1 + 2 + 8 + 8 + 8)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLaunchpad<'info> {
+    #[account(mut, has_one = admin)]
+    pub launchpad: Account<'info, Launchpad>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveSale<'info> {
+    #[account(has_one = admin)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QueueAction<'info> {
+    #[account(has_one = admin)]
+    pub launchpad: Account<'info, Launchpad>,
+    // One queued action at a time
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + AdminAction::LEN + 8 + 1,
+        seeds = [PENDING_ACTION_SEED, launchpad.key().as_ref()],
+        bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteAction<'info> {
+    #[account(mut, has_one = admin)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(
+        mut,
+        seeds = [PENDING_ACTION_SEED, launchpad.key().as_ref()],
+        bump = pending_action.bump,
+        has_one = launchpad,
+        close = admin
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AllowMint<'info> {
+    #[account(has_one = admin)]
+    pub launchpad: Account<'info, Launchpad>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 32 + 1,
+        seeds = [ALLOWED_MINT_SEED, launchpad.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub allowed_mint: Account<'info, AllowedMint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeMint<'info> {
+    #[account(has_one = admin)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad, close = admin)]
+    pub allowed_mint: Account<'info, AllowedMint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(soft_cap: u64, hard_cap: u64, mint: Pubkey)]
+pub struct RegisterToken<'info> {
+    #[account(mut)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(init, payer = registrant, space = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 1 + 1 + 8 + 8 + 2 + 32 + 8 + 4 + MAX_ACCEPTED_PAYMENTS * (32 + 8) + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 4 + 1 + 8 + 8 + 1 + VestingTerms::LEN + 1 + 8 + 1 + 8 + 1 + 1 + 32 + 8 + 8 + 8 + 32 + 1 + 1 + 1 + 32 + 1 + 1 + 8 + 1 + 32 + 1 + 8 + 1 + 1 + 8 + 8 + 1 + 1 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    #[account(address = mint)]
+    pub token_mint: Account<'info, Mint>, // Changed from Token to Mint
+    // Required when the launchpad is curated
+    #[account(
+        seeds = [ALLOWED_MINT_SEED, launchpad.key().as_ref(), token_mint.key().as_ref()],
+        bump = allowed_mint.bump
+    )]
+    pub allowed_mint: Option<Account<'info, AllowedMint>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitVault<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositSaleTokens<'info> {
+    #[account(mut, has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(address = token_sale.token_mint)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = registrant
+    )]
+    pub registrant_token_account: Account<'info, TokenAccount>,
+    pub registrant: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    // Passed to add the deposit to a round's tokens_available
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Option<Account<'info, SaleRound>>,
+}
+
+#[derive(Accounts)]
+#[instruction(investor: Pubkey)]
+pub struct SetPriceOverride<'info> {
+    #[account(has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(
+        init_if_needed,
+        payer = registrant,
+        space = 8 + Contribution::LEN,
+        seeds = [CONTRIBUTION_SEED, token_sale.key().as_ref(), investor.as_ref()],
+        bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(investor: Pubkey)]
+pub struct VerifyInvestor<'info> {
+    #[account(has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(
+        init,
+        payer = registrant,
+        space = 8 + 32 + 32 + 1,
+        seeds = [VERIFICATION_SEED, token_sale.key().as_ref(), investor.as_ref()],
+        bump
+    )]
+    pub verification: Account<'info, Verification>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptRegistrant<'info> {
+    #[account(mut)]
+    pub token_sale: Account<'info, TokenSale>,
+    pub new_registrant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRefundWindow<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad, has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    pub registrant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSale<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = registrant, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    pub registrant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddSaleRound<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    // Grown by one key per round so round_keys always fits
+    #[account(
+        mut,
+        has_one = registrant,
+        has_one = launchpad,
+        realloc = token_sale.to_account_info().data_len() + 32,
+        realloc::payer = registrant,
+        realloc::zero = false
+    )]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(init, payer = registrant, space = 8 + SaleRound::LEN)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(configs: Vec<RoundConfig>)]
+pub struct AddSaleRounds<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(
+        mut,
+        has_one = registrant,
+        has_one = launchpad,
+        realloc = token_sale.to_account_info().data_len() + 32 * configs.len(),
+        realloc::payer = registrant,
+        realloc::zero = false
+    )]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSaleRound<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = registrant, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    pub registrant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ActivateSaleRound<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = registrant, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(requested: u64)]
+pub struct PurchaseTokens<'info> {
+    #[account(mut, has_one = treasury)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(mut, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
+    #[account(mut)]
+    pub treasury: SystemAccount<'info>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(address = token_sale.token_mint)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    /// CHECK: The investor's ATA for the sale mint, created by the handler if missing
+    #[account(mut, address = get_associated_token_address(&investor.key(), &token_mint.key()))]
+    pub investor_token_account: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = 8 + Contribution::LEN,
+        seeds = [CONTRIBUTION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(
+        init,
+        payer = investor,
+        space = 8 + VestingSchedule::LEN,
+        seeds = [
+            VESTING_SEED,
+            token_sale.key().as_ref(),
+            sale_round.key().as_ref(),
+            investor.key().as_ref(),
+            &contribution.vesting_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = 8 + 32 + 32 + 1,
+        seeds = [INVESTOR_SEED, launchpad.key().as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub investor_marker: Account<'info, InvestorMarker>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// CHECK: Instructions sysvar, read for KYC attestations
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    // Only passed when paying with an accepted SPL token instead of SOL
+    pub payment_mint: Option<Account<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = investor
+    )]
+    pub investor_payment_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_payment_account: Option<Account<'info, TokenAccount>>,
+    // Required when the sale requires verification
+    #[account(
+        seeds = [VERIFICATION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump = verification.bump
+    )]
+    pub verification: Option<Account<'info, Verification>>,
+    // Required when the sale weights allocations by points
+    #[account(
+        constraint = points_account.mint == token_sale.points_mint @ LaunchpadError::InvalidPointsAccount,
+        constraint = points_account.owner == investor.key() @ LaunchpadError::InvalidPointsAccount
+    )]
+    pub points_account: Option<Account<'info, TokenAccount>>,
+    // Required when the sale wraps SOL contributions
+    #[account(
+        mut,
+        constraint = vault_wsol_account.mint == token::spl_token::native_mint::ID @ LaunchpadError::WsolAccountRequired,
+        constraint = vault_wsol_account.owner == vault.key() @ LaunchpadError::WsolAccountRequired
+    )]
+    pub vault_wsol_account: Option<Account<'info, TokenAccount>>,
+    // Required when the sale pays purchases directly into custody
+    #[account(
+        mut,
+        address = token_sale.custody_authority @ LaunchpadError::InvalidCustody
+    )]
+    pub custody: Option<SystemAccount<'info>>,
+    // Required when the round caps allocations at a share of its tokens
+    #[account(
+        mut,
+        seeds = [ROUND_POSITION_SEED, sale_round.key().as_ref(), investor.key().as_ref()],
+        bump = round_position.bump
+    )]
+    pub round_position: Option<Account<'info, RoundPosition>>,
+    // Required when the round pays into its own vault
+    #[account(mut, seeds = [ROUND_VAULT_SEED, sale_round.key().as_ref()], bump = sale_round.vault_bump)]
+    pub round_vault: Option<SystemAccount<'info>>,
+    // Pays for the investor's ATA when the sale's ata_payer isn't the investor
+    #[account(mut)]
+    pub ata_sponsor: Option<Signer<'info>>,
+    // Required when paying with an accepted SPL token
+    #[account(mut, has_one = token_sale, has_one = investor)]
+    pub payment_position: Option<Account<'info, PaymentPosition>>,
+}
+
+#[derive(Accounts)]
+pub struct OpenRoundPosition<'info> {
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        init,
+        payer = investor,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [ROUND_POSITION_SEED, sale_round.key().as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub round_position: Account<'info, RoundPosition>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct EnableRoundVault<'info> {
+    #[account(has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(mut, seeds = [ROUND_VAULT_SEED, sale_round.key().as_ref()], bump)]
+    pub round_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(beneficiary: Pubkey)]
+pub struct CreateVestingGrant<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = registrant, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        init_if_needed,
+        payer = registrant,
+        space = 8 + Contribution::LEN,
+        seeds = [CONTRIBUTION_SEED, token_sale.key().as_ref(), beneficiary.as_ref()],
+        bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(
+        init,
+        payer = registrant,
+        space = 8 + VestingSchedule::LEN,
+        seeds = [
+            VESTING_SEED,
+            token_sale.key().as_ref(),
+            sale_round.key().as_ref(),
+            beneficiary.as_ref(),
+            &contribution.vesting_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(init, payer = admin, space = 8 + 32 + 8)]
+pub struct Commit<'info> {
     pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = 8 + 32 + 32 + 8 + 1 + 1,
+        seeds = [COMMITMENT_SEED, sale_round.key().as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub commitment: Account<'info, Commitment>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
     #[account(mut)]
-    pub admin: Signer<'info>,
+    pub investor: Signer<'info>,
     pub system_program: Program<'info, System>,
+    /// CHECK: Instructions sysvar, read for KYC attestations
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    // Required when the sale requires verification
+    #[account(
+        seeds = [VERIFICATION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump = verification.bump
+    )]
+    pub verification: Option<Account<'info, Verification>>,
 }
 
 #[derive(Accounts)]
-pub struct RegisterToken<'info> {
+pub struct CloseRound<'info> {
+    // Shrunk by the closed round's key
+    #[account(
+        mut,
+        has_one = registrant,
+        realloc = token_sale.to_account_info().data_len() - 32,
+        realloc::payer = registrant,
+        realloc::zero = false
+    )]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale, close = registrant)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(mut)]
+    pub registrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureRound<'info> {
+    #[account(mut, has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    pub registrant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CommitBid<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        init,
+        payer = investor,
+        space = 8 + 32 + 32 + 32 + 1 + 1,
+        seeds = [BID_SEED, sale_round.key().as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, Bid>,
     #[account(mut)]
+    pub investor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Instructions sysvar, read for KYC attestations
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    // Required when the sale requires verification
+    #[account(
+        seeds = [VERIFICATION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump = verification.bump
+    )]
+    pub verification: Option<Account<'info, Verification>>,
+}
+
+#[derive(Accounts)]
+pub struct RevealBid<'info> {
     pub launchpad: Account<'info, Launchpad>,
-    #[account(init, payer = registrant, space = 8 + 32 + 32 + 8 + 8 + 8 + 1)]
+    #[account(mut, has_one = launchpad)]
     pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        mut,
+        seeds = [BID_SEED, sale_round.key().as_ref(), investor.key().as_ref()],
+        bump = bid.bump
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = 8 + 32 + 32 + 8 + 1 + 1,
+        seeds = [COMMITMENT_SEED, sale_round.key().as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub commitment: Account<'info, Commitment>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
     #[account(mut)]
-    pub registrant: Signer<'info>,
-    pub token_mint: Account<'info, Mint>, // Changed from Token to Mint
+    pub investor: Signer<'info>,
     pub system_program: Program<'info, System>,
+    /// CHECK: Instructions sysvar, read for KYC attestations
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    // Required when the sale requires verification
+    #[account(
+        seeds = [VERIFICATION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump = verification.bump
+    )]
+    pub verification: Option<Account<'info, Verification>>,
 }
 
 #[derive(Accounts)]
-pub struct AddSaleRound<'info> {
+pub struct FinalizeAllocation<'info> {
     #[account(mut)]
     pub token_sale: Account<'info, TokenSale>,
-    #[account(init, payer = registrant, space = 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1)]
+    #[account(mut, has_one = token_sale)]
     pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        mut,
+        seeds = [COMMITMENT_SEED, sale_round.key().as_ref(), investor.key().as_ref()],
+        bump = commitment.bump,
+        has_one = sale_round,
+        has_one = investor
+    )]
+    pub commitment: Account<'info, Commitment>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Contribution::LEN,
+        seeds = [CONTRIBUTION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + VestingSchedule::LEN,
+        seeds = [
+            VESTING_SEED,
+            token_sale.key().as_ref(),
+            sale_round.key().as_ref(),
+            investor.key().as_ref(),
+            &contribution.vesting_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    // Receives the refunded excess; settlement doesn't need their signature
+    #[account(mut)]
+    pub investor: SystemAccount<'info>,
+    // Pays rent for the contribution and vesting accounts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeSale<'info> {
+    #[account(mut)]
+    pub token_sale: Account<'info, TokenSale>,
+}
+
+#[derive(Accounts)]
+pub struct Refund<'info> {
+    #[account(has_one = treasury)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(
+        mut,
+        seeds = [CONTRIBUTION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump = contribution.bump,
+        has_one = token_sale,
+        has_one = investor,
+        close = investor
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
+    #[account(mut)]
+    pub treasury: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPaymentPosition<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = investor,
+        space = 8 + 32 + 32 + 32 + 8 + 8 + 1,
+        seeds = [
+            PAYMENT_POSITION_SEED,
+            token_sale.key().as_ref(),
+            investor.key().as_ref(),
+            payment_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub payment_position: Account<'info, PaymentPosition>,
     #[account(mut)]
+    pub investor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawPayments<'info> {
+    #[account(has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_payment_account: Account<'info, TokenAccount>,
+    // Owned by the registrant, or the custody authority when one is set
+    #[account(mut, token::mint = payment_mint)]
+    pub destination: Account<'info, TokenAccount>,
     pub registrant: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RefundPayment<'info> {
+    #[account(mut)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(address = payment_position.mint)]
+    pub payment_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [
+            PAYMENT_POSITION_SEED,
+            token_sale.key().as_ref(),
+            investor.key().as_ref(),
+            payment_mint.key().as_ref()
+        ],
+        bump = payment_position.bump,
+        has_one = token_sale,
+        has_one = investor,
+        close = investor
+    )]
+    pub payment_position: Account<'info, PaymentPosition>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_payment_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = investor
+    )]
+    pub investor_payment_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPurchase<'info> {
+    #[account(has_one = treasury)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, address = vesting.sale_round)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        mut,
+        has_one = token_sale,
+        has_one = investor,
+        close = investor
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+    #[account(
+        mut,
+        seeds = [CONTRIBUTION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump = contribution.bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
+    #[account(mut)]
+    pub treasury: SystemAccount<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ActivateSaleRound<'info> {
+pub struct RefundRoundPurchase<'info> {
+    #[account(has_one = treasury)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, address = vesting.sale_round)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(
+        mut,
+        has_one = token_sale,
+        has_one = investor,
+        close = investor
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+    #[account(
+        mut,
+        seeds = [CONTRIBUTION_SEED, token_sale.key().as_ref(), investor.key().as_ref()],
+        bump = contribution.bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(mut, seeds = [ROUND_VAULT_SEED, sale_round.key().as_ref()], bump = sale_round.vault_bump)]
+    pub round_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
     #[account(mut)]
+    pub treasury: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendVesting<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(has_one = launchpad, has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub vesting: Account<'info, VestingSchedule>,
+    pub registrant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TransferVesting<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale, has_one = investor)]
+    pub vesting: Account<'info, VestingSchedule>,
+    pub investor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DisplayRate<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(has_one = token_sale)]
     pub sale_round: Account<'info, SaleRound>,
+}
+
+#[derive(Accounts)]
+pub struct AvgPrice<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(has_one = token_sale)]
+    pub contribution: Account<'info, Contribution>,
+}
+
+#[derive(Accounts)]
+pub struct RemainingCapacity<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+}
+
+#[derive(Accounts)]
+pub struct AuditSale<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+}
+
+#[derive(Accounts)]
+pub struct GetVestingStats<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+}
+
+#[derive(Accounts)]
+pub struct GetInvestorPositions<'info> {
+    /// CHECK: Only compared against each schedule's investor
+    pub investor: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTokens<'info> {
+    pub launchpad: Account<'info, Launchpad>,
+    // Must sit at its canonical address, so clients can derive it without indexing
+    #[account(
+        mut,
+        has_one = token_sale,
+        has_one = investor,
+        seeds = [
+            VESTING_SEED,
+            token_sale.key().as_ref(),
+            vesting.sale_round.as_ref(),
+            vesting.seed_investor.as_ref(),
+            &vesting.nonce.to_le_bytes()
+        ],
+        bump = vesting.bump
+    )]
+    pub vesting: Account<'info, VestingSchedule>,
+    #[account(mut, has_one = launchpad)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(address = token_sale.token_mint)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = investor
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: The investor's staking escrow PDA, only passed to claim-and-stake
+    #[account(seeds = [STAKE_SEED, investor.key().as_ref()], bump)]
+    pub stake_escrow: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = stake_escrow
+    )]
+    pub stake_escrow_token_account: Option<Account<'info, TokenAccount>>,
+    // Sends the claim to another wallet's token account instead
+    #[account(mut, token::mint = token_mint)]
+    pub recipient_token_account: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    pub investor: Signer<'info>,
+    /// CHECK: The investor's staking escrow PDA, which signs for its token accounts
+    #[account(seeds = [STAKE_SEED, investor.key().as_ref()], bump)]
+    pub stake_escrow: UncheckedAccount<'info>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = stake_escrow
+    )]
+    pub stake_escrow_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint, token::authority = investor)]
+    pub investor_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawRaised<'info> {
+    #[account(mut, has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
     #[account(mut)]
     pub registrant: Signer<'info>,
+    // Required when the sale has a custody authority
+    #[account(
+        mut,
+        address = token_sale.custody_authority @ LaunchpadError::InvalidCustody
+    )]
+    pub custody: Option<SystemAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> PurchaseTokens<'info> {
+    // The account funding the investor's ATA under the sale's ata_payer policy
+    pub fn ata_payer(&self) -> Result<AccountInfo<'info>> {
+        let sponsor = match self.token_sale.ata_payer {
+            AtaPayer::Investor => return Ok(self.investor.to_account_info()),
+            AtaPayer::Registrant => self.token_sale.registrant,
+            AtaPayer::Treasury => self.treasury.key(),
+        };
+        let ata_sponsor = self
+            .ata_sponsor
+            .as_ref()
+            .ok_or(LaunchpadError::InvalidAtaSponsor)?;
+        require_keys_eq!(
+            ata_sponsor.key(),
+            sponsor,
+            LaunchpadError::InvalidAtaSponsor
+        );
+        Ok(ata_sponsor.to_account_info())
+    }
+}
+
+impl<'info> WithdrawRaised<'info> {
+    // Withdrawals go to the custody authority when one is set, else the registrant
+    pub fn payee(&self) -> Result<AccountInfo<'info>> {
+        if self.token_sale.custody_authority == Pubkey::default() {
+            return Ok(self.registrant.to_account_info());
+        }
+        Ok(self
+            .custody
+            .as_ref()
+            .ok_or(LaunchpadError::InvalidCustody)?
+            .to_account_info())
+    }
+}
+
+#[derive(Accounts)]
+pub struct UnwrapVaultSol<'info> {
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        constraint = vault_wsol_account.mint == token::spl_token::native_mint::ID @ LaunchpadError::WsolAccountRequired,
+        constraint = vault_wsol_account.owner == vault.key() @ LaunchpadError::WsolAccountRequired
+    )]
+    pub vault_wsol_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-#[instruction(amount: u64)]
-pub struct PurchaseTokens<'info> {
-    #[account(mut)]
-    pub sale_round: Account<'info, SaleRound>,
-    #[account(mut)]
+pub struct SweepVaultDust<'info> {
+    #[account(has_one = treasury)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(has_one = launchpad)]
     pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
     #[account(mut)]
-    pub investor: Signer<'info>,
-    /// CHECK: Safe because this is just a native system account
-    #[account(mut)]
-    pub vault: UncheckedAccount<'info>,
+    pub treasury: SystemAccount<'info>,
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimAbandoned<'info> {
+    #[account(has_one = admin, has_one = treasury)]
+    pub launchpad: Account<'info, Launchpad>,
+    #[account(mut, has_one = launchpad, has_one = registrant, close = treasury)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(address = token_sale.token_mint)]
     pub token_mint: Account<'info, Mint>,
     #[account(
         mut,
@@ -240,31 +4659,59 @@ pub struct PurchaseTokens<'info> {
     #[account(
         mut,
         associated_token::mint = token_mint,
-        associated_token::authority = investor
-    )]
-    pub investor_token_account: Account<'info, TokenAccount>,
-    #[account(
-        init,
-        payer = investor,
-        space = 8 + 32 + 8 + 8 + 8 + 8
+        associated_token::authority = registrant
     )]
-    pub vesting: Account<'info, VestingSchedule>,
+    pub registrant_token_account: Account<'info, TokenAccount>,
+    pub registrant: SystemAccount<'info>,
+    #[account(mut)]
+    pub treasury: SystemAccount<'info>,
+    pub admin: Signer<'info>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimTokens<'info> {
-    #[account(mut)]
-    pub vesting: Account<'info, VestingSchedule>,
-    #[account(mut)]
+pub struct WithdrawRound<'info> {
+    #[account(has_one = registrant)]
     pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(mut, seeds = [ROUND_VAULT_SEED, sale_round.key().as_ref()], bump = sale_round.vault_bump)]
+    pub round_vault: SystemAccount<'info>,
     #[account(mut)]
-    pub investor: Signer<'info>,
-    #[account(mut)]
-    pub vault: SystemAccount<'info>, // Added vault account
-    pub token_mint: Account<'info, Mint>, // Added token_mint account
+    pub registrant: Signer<'info>,
+    // Required when the sale has a custody authority
+    #[account(
+        mut,
+        address = token_sale.custody_authority @ LaunchpadError::InvalidCustody
+    )]
+    pub custody: Option<SystemAccount<'info>>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> WithdrawRound<'info> {
+    pub fn payee(&self) -> Result<AccountInfo<'info>> {
+        if self.token_sale.custody_authority == Pubkey::default() {
+            return Ok(self.registrant.to_account_info());
+        }
+        Ok(self
+            .custody
+            .as_ref()
+            .ok_or(LaunchpadError::InvalidCustody)?
+            .to_account_info())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ReclaimUnsold<'info> {
+    #[account(mut, has_one = registrant)]
+    pub token_sale: Account<'info, TokenSale>,
+    #[account(mut, has_one = token_sale)]
+    pub sale_round: Account<'info, SaleRound>,
+    #[account(seeds = [VAULT_SEED, token_sale.key().as_ref()], bump = token_sale.vault_bump)]
+    pub vault: SystemAccount<'info>,
+    #[account(address = token_sale.token_mint)]
+    pub token_mint: Account<'info, Mint>,
     #[account(
         mut,
         associated_token::mint = token_mint,
@@ -274,9 +4721,10 @@ pub struct ClaimTokens<'info> {
     #[account(
         mut,
         associated_token::mint = token_mint,
-        associated_token::authority = investor
+        associated_token::authority = registrant
     )]
-    pub investor_token_account: Account<'info, TokenAccount>,
+    pub registrant_token_account: Account<'info, TokenAccount>,
+    pub registrant: Signer<'info>,
     pub token_program: Program<'info, Token>,
 }
 
@@ -285,20 +4733,388 @@ pub struct ClaimTokens<'info> {
 pub struct Launchpad {
     pub admin: Pubkey,
     pub total_projects: u64,
+    pub treasury: Pubkey,
+    pub min_round_lead: i64,
+    pub curated: bool,
+    pub claim_cooldown: i64,
+    pub block_self_purchase: bool,
+    // Platform fee on SOL contributions, in basis points
+    pub fee_bps: u16,
+    pub total_investors: u64,
+    pub max_total_investors: u64,
+    pub min_price_per_token: u64,
+    pub version: u8,
+    // Share of refunds and cancelled purchases kept for the treasury, in basis points
+    pub refund_fee_bps: u16,
+    // Wait before a queued admin action may execute (0 lets fee increases apply directly)
+    pub timelock_delay: i64,
+    // Longest vesting rounds and grants may configure, in seconds (0 disables)
+    pub max_vesting_duration: u64,
+    // Shortest refund window a sale may set, so investors get time to claim
+    pub min_refund_window: i64,
+}
+
+impl Launchpad {
+    pub fn check_vesting_duration(&self, duration: u64) -> Result<()> {
+        if self.max_vesting_duration > 0 && duration > self.max_vesting_duration {
+            msg!(
+                "Vesting duration {} above maximum {}",
+                duration,
+                self.max_vesting_duration
+            );
+            return err!(LaunchpadError::VestingTooLong);
+        }
+        Ok(())
+    }
+
+    // Apply an admin change and emit its audit event
+    pub fn apply(&mut self, launchpad: Pubkey, action: &AdminAction) -> Result<()> {
+        match *action {
+            AdminAction::SetFeeConfig { fee_bps, treasury } => {
+                emit!(FeeConfigUpdated {
+                    launchpad,
+                    old_fee_bps: self.fee_bps,
+                    old_treasury: self.treasury,
+                    fee_bps,
+                    treasury,
+                });
+                self.fee_bps = fee_bps;
+                self.treasury = treasury;
+            }
+            AdminAction::SetRefundFeeBps { refund_fee_bps } => {
+                emit!(LaunchpadSettingChanged {
+                    launchpad,
+                    setting: LaunchpadSetting::RefundFeeBps,
+                    old_value: self.refund_fee_bps.into(),
+                    new_value: refund_fee_bps.into(),
+                });
+                self.refund_fee_bps = refund_fee_bps;
+            }
+            AdminAction::SetTimelockDelay { timelock_delay } => {
+                emit!(LaunchpadSettingChanged {
+                    launchpad,
+                    setting: LaunchpadSetting::TimelockDelay,
+                    old_value: self.timelock_delay as u64,
+                    new_value: timelock_delay as u64,
+                });
+                self.timelock_delay = timelock_delay;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Admin changes that can be queued behind the timelock
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    SetFeeConfig { fee_bps: u16, treasury: Pubkey },
+    SetRefundFeeBps { refund_fee_bps: u16 },
+    SetTimelockDelay { timelock_delay: i64 },
+}
+
+impl AdminAction {
+    pub const LEN: usize = 1 + 2 + 32;
+
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            AdminAction::SetFeeConfig { fee_bps, treasury } => {
+                require!(fee_bps <= 10_000, LaunchpadError::InvalidFeeConfig);
+                require!(
+                    treasury != Pubkey::default(),
+                    LaunchpadError::InvalidFeeConfig
+                );
+            }
+            AdminAction::SetRefundFeeBps { refund_fee_bps } => {
+                require!(refund_fee_bps <= 10_000, LaunchpadError::InvalidFeeConfig);
+            }
+            AdminAction::SetTimelockDelay { timelock_delay } => {
+                require!(timelock_delay >= 0, LaunchpadError::InvalidTimelockDelay);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[account]
+pub struct PendingAction {
+    pub launchpad: Pubkey,
+    pub action: AdminAction,
+    pub execute_after: i64,
+    pub bump: u8,
 }
 
 #[account]
 pub struct TokenSale {
+    pub launchpad: Pubkey,
     pub registrant: Pubkey,
     pub token_mint: Pubkey,
     pub soft_cap: u64,
     pub hard_cap: u64,
     pub total_raised: u64,
     pub is_active: bool,
+    pub status: SaleStatus,
+    pub end_time: i64,
+    pub finalization_grace: i64,
+    // Number of rounds currently open for purchases
+    pub active_rounds: u16,
+    pub kyc_authority: Pubkey,
+    pub finalized_at: i64,
+    pub accepted_payments: Vec<PaymentConfig>,
+    pub vault_bump: u8,
+    pub withdrawn: u64,
+    pub total_refunded: u64,
+    pub total_committed: u64,
+    pub committed_settled: u64,
+    pub withdraw_stream_duration: i64,
+    pub tokens_deposited: u64,
+    pub cooloff_secs: i64,
+    // Every round created for the sale, in creation order
+    pub round_keys: Vec<Pubkey>,
+    pub transferable: bool,
+    pub tokens_sold: u64,
+    pub deposit_deadline: i64,
+    pub auto_finalize_on_hardcap: bool,
+    pub default_vesting: VestingTerms,
+    pub claims_enabled_after_finalize: bool,
+    pub token_hard_cap: u64,
+    pub version: u8,
+    // Extra time granted once when the sale ends below its soft cap
+    pub softcap_extension: i64,
+    pub softcap_extended: bool,
+    // Purchases need a registrant-issued Verification for the investor
+    pub require_verification: bool,
+    // Investors may buy up to tokens_per_point per unit of this mint they hold
+    pub points_mint: Pubkey,
+    pub tokens_per_point: u64,
+    // Totals carried over from closed rounds so audit_sale still balances
+    pub closed_rounds_raised: u64,
+    pub closed_rounds_sold: u64,
+    // Nominated by transfer_registrant, becomes registrant on accept
+    pub pending_registrant: Pubkey,
+    // Reject claims routed anywhere but the investor's own ATA
+    pub claim_to_self_only: bool,
+    // SOL contributions are held as wSOL until unwrap_vault_sol
+    pub wrap_sol: bool,
+    // Whether investors refunded through cancel_purchase may purchase again
+    pub reentry_allowed: bool,
+    // Hash of the off-chain sale terms, fixed at registration
+    pub terms_hash: [u8; 32],
+    // Buyers must pass terms_hash to purchase, showing they saw the terms
+    pub enforce_terms_hash: bool,
+    // Decimals of token_mint; prices are lamports per whole token
+    pub token_decimals: u8,
+    // Tokens that must be sold for success (0 disables), combined with the
+    // soft cap per success_criteria
+    pub min_tokens_sold: u64,
+    pub success_criteria: SuccessCriteria,
+    // Receives withdrawals instead of the registrant, e.g. a multisig (default: none)
+    pub custody_authority: Pubkey,
+    // Purchases pay custody_authority directly, bypassing the vault
+    pub direct_custody: bool,
+    // Registration time; no vesting may start before it
+    pub created_at: i64,
+    // Whether an active sale still takes purchases, independent of status
+    pub state: SaleState,
+    // Admin sign-off, required to activate rounds on a curated launchpad
+    pub approved: bool,
+    // Tokens paid out by claim_tokens, never more than tokens_deposited
+    pub tokens_claimed: u64,
+    // Seconds after failure that refunds stay open; 0 leaves them open
+    pub refund_window: i64,
+    // Whether deposits may add inventory to a round that's already selling
+    pub active_topup_allowed: bool,
+    // Several rounds may be active at once; when off, only one at a time
+    pub allow_overlapping_rounds: bool,
+    // Most tokens a single claim delivers (0 disables)
+    pub max_claim_per_tx: u64,
+    // Who pays the rent when purchase_tokens creates the investor's ATA
+    pub ata_payer: AtaPayer,
+    // Most SOL accepted per velocity_window before purchases are wound down (0 disables)
+    pub velocity_limit: u64,
+    pub velocity_window: i64,
+    // Start of the current window and the SOL raised in it so far
+    pub window_start: i64,
+    pub window_raised: u64,
+    // SOL value of everything paid in accepted SPL tokens, counted against the hard cap
+    pub payment_value_raised: u64,
+    // Capacity held for rounds whose commitments are partway through settlement
+    pub committed_reserved: u64,
+    // Part of total_raised held in isolated rounds' own vaults, never the sale vault
+    pub isolated_raised: u64,
+    // Tokens handed out by vesting grants, owed from the vault like sold ones
+    pub tokens_granted: u64,
+}
+
+impl TokenSale {
+    // Tokens the vault must hold for every purchase and grant to be claimable
+    pub fn tokens_owed(&self) -> u64 {
+        self.tokens_sold.saturating_add(self.tokens_granted)
+    }
+
+    // Whether the sale met its soft cap and token threshold, per success_criteria
+    pub fn targets_met(&self) -> bool {
+        let raised = self.total_raised.saturating_add(self.payment_value_raised) >= self.soft_cap;
+        if self.min_tokens_sold == 0 {
+            return raised;
+        }
+        let sold = self.tokens_sold >= self.min_tokens_sold;
+        match self.success_criteria {
+            SuccessCriteria::All => raised && sold,
+            SuccessCriteria::Any => raised || sold,
+        }
+    }
+
+    pub fn finalize(&mut self, now: i64) {
+        self.status = if self.targets_met() {
+            SaleStatus::Succeeded
+        } else {
+            SaleStatus::Failed
+        };
+        self.active_rounds = 0;
+        self.finalized_at = now;
+    }
+
+    // A round's own vesting terms, falling back to the sale default
+    pub fn vesting_for(&self, sale_round: &SaleRound) -> VestingTerms {
+        sale_round.vesting.unwrap_or(self.default_vesting)
+    }
+
+    pub fn finalized_event(&self, sale: Pubkey) -> SaleFinalized {
+        SaleFinalized {
+            sale,
+            status: self.status,
+            total_raised: self.total_raised,
+            soft_cap: self.soft_cap,
+            withdrawals_enabled: self.status == SaleStatus::Succeeded,
+            refunds_enabled: self.status == SaleStatus::Failed,
+        }
+    }
+
+    pub fn max_tokens_for_points(&self, points: u64) -> u64 {
+        (points as u128 * self.tokens_per_point as u128).min(u64::MAX as u128) as u64
+    }
+
+    // Add SOL to the current velocity window, starting a new one once it has
+    // passed; true when the window's inflow exceeds velocity_limit
+    pub fn record_inflow(&mut self, amount: u64, now: i64) -> bool {
+        if self.velocity_limit == 0 {
+            return false;
+        }
+        if now >= self.window_start.saturating_add(self.velocity_window) {
+            self.window_start = now;
+            self.window_raised = 0;
+        }
+        self.window_raised = self.window_raised.saturating_add(amount);
+        self.window_raised > self.velocity_limit
+    }
+
+    // A soft-cap extension keeps active rounds selling until the sale's new end
+    pub fn round_end(&self, sale_round: &SaleRound) -> i64 {
+        if self.softcap_extended {
+            sale_round.end_time.max(self.end_time)
+        } else {
+            sale_round.end_time
+        }
+    }
+
+    // Raised SOL that went through the sale vault; isolated rounds pay out their own
+    pub fn vault_raised(&self) -> u64 {
+        self.total_raised.saturating_sub(self.isolated_raised)
+    }
+
+    // Raised SOL released to the registrant by `now`, linear from finalization
+    pub fn streamed_at(&self, now: i64) -> u64 {
+        let raised = self.vault_raised();
+        let elapsed = now.saturating_sub(self.finalized_at);
+        if self.withdraw_stream_duration == 0 || elapsed >= self.withdraw_stream_duration {
+            return raised;
+        }
+        if elapsed <= 0 {
+            return 0;
+        }
+        (raised as u128 * elapsed as u128 / self.withdraw_stream_duration as u128) as u64
+    }
+
+    pub fn remaining_capacity(&self) -> u64 {
+        self.hard_cap
+            .saturating_sub(self.total_raised)
+            .saturating_sub(self.payment_value_raised)
+            .saturating_sub(self.committed_reserved)
+    }
+
+    // Refunds close `refund_window` seconds after the sale failed (0 never closes them)
+    pub fn check_refund_window(&self, now: i64) -> Result<()> {
+        if self.refund_window > 0 {
+            let closes_at = self
+                .finalized_at
+                .checked_add(self.refund_window)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            require!(now < closes_at, LaunchpadError::RefundWindowClosed);
+        }
+        Ok(())
+    }
+
+    // Terminal, with raised funds either fully withdrawn or fully refunded
+    pub fn is_settled(&self) -> bool {
+        match self.status {
+            SaleStatus::Succeeded => self.withdrawn >= self.vault_raised(),
+            SaleStatus::Failed => self.total_raised == 0,
+            SaleStatus::Active => false,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct PaymentConfig {
+    pub mint: Pubkey,
+    pub price_per_token: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchpadSetting {
+    RefundFeeBps,
+    MinPricePerToken,
+    MaxTotalInvestors,
+    BlockSelfPurchase,
+    ClaimCooldown,
+    Curated,
+    MinRoundLead,
+    TimelockDelay,
+    MaxVestingDuration,
+    MinRefundWindow,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SaleStatus {
+    Active,
+    Succeeded,
+    Failed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SaleState {
+    Open,
+    // No new purchases; existing investors may still claim and refund
+    WindingDown,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AtaPayer {
+    Investor,
+    Registrant,
+    Treasury,
+}
+
+// How the soft cap and min_tokens_sold combine to decide success
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SuccessCriteria {
+    All,
+    Any,
 }
 
 #[account]
 pub struct SaleRound {
+    pub token_sale: Pubkey,
     pub price_per_token: u64,
     pub tokens_available: u64,
     pub tokens_sold: u64,
@@ -307,13 +5123,574 @@ pub struct SaleRound {
     pub start_time: i64,
     pub end_time: i64,
     pub is_active: bool,
+    pub rounding: RoundingMode,
+    pub allowed_tiers: [u64; 4],
+    // Overrides the sale's default vesting when set
+    pub vesting: Option<VestingTerms>,
+    // Most tokens a single purchase may buy (0 disables)
+    pub max_tokens_per_tx: u64,
+    // Sell what's left and charge only for it instead of rejecting oversized buys
+    pub partial_fill_allowed: bool,
+    // Final stretch of the round reserved for revealing sealed bids
+    pub reveal_window: i64,
+    pub version: u8,
+    pub max_total_extension: i64,
+    pub total_extended: i64,
+    // SOL raised through this round, summed by audit_sale against total_raised
+    pub sol_raised: u64,
+    // Part of tokens_available only grants and guaranteed allocations may draw from
+    pub reserved_tokens: u64,
+    // Most of the round's tokens one investor may buy, in basis points (0 disables)
+    pub max_alloc_bps: u16,
+    // Purchases pay into the round's own vault and settle against its soft cap
+    pub isolated: bool,
+    pub vault_bump: u8,
+    pub soft_cap: u64,
+    pub vault_withdrawn: bool,
+    // Commitments made to the round, how much of them has been settled, and
+    // the SOL they fill in total, fixed by the first settlement
+    pub total_committed: u64,
+    pub committed_settled: u64,
+    pub commit_fillable: u64,
+}
+
+impl SaleRound {
+    pub const LEN: usize = 32
+        + 8 * 7
+        + 2
+        + 8 * 4
+        + 1
+        + VestingTerms::LEN
+        + 8
+        + 1
+        + 8
+        + 1
+        + 8 * 4
+        + 2
+        + 1
+        + 1
+        + 8
+        + 1
+        + 8 * 3;
+
+    pub fn from_config(token_sale: Pubkey, config: &RoundConfig) -> Self {
+        Self {
+            token_sale,
+            price_per_token: config.price_per_token,
+            tokens_available: config.tokens_available,
+            tokens_sold: 0,
+            min_contribution: config.min_contribution,
+            max_contribution: config.max_contribution,
+            start_time: config.start_time,
+            end_time: config.end_time,
+            is_active: false,
+            rounding: config.rounding,
+            allowed_tiers: config.allowed_tiers,
+            vesting: config.vesting,
+            max_tokens_per_tx: config.max_tokens_per_tx,
+            partial_fill_allowed: config.partial_fill_allowed,
+            reveal_window: 0,
+            version: ACCOUNT_VERSION,
+            max_total_extension: 0,
+            total_extended: 0,
+            sol_raised: 0,
+            reserved_tokens: 0,
+            max_alloc_bps: 0,
+            isolated: false,
+            vault_bump: 0,
+            soft_cap: 0,
+            vault_withdrawn: false,
+            total_committed: 0,
+            committed_settled: 0,
+            commit_fillable: 0,
+        }
+    }
+
+    // Commitments join the round's pro-rata pool
+    pub fn add_commitment(&mut self, token_sale: &mut TokenSale, amount: u64) -> Result<()> {
+        self.total_committed = self
+            .total_committed
+            .checked_add(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        token_sale.total_committed = token_sale
+            .total_committed
+            .checked_add(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        Ok(())
+    }
+
+    // Share of commit_fillable due to `amount` settling after everything already
+    // settled; taken off the running total so the shares sum to it exactly
+    pub fn fill_share(&self, amount: u64) -> Result<u64> {
+        let filled_through = |settled: u64| {
+            (settled as u128 * self.commit_fillable as u128 / self.total_committed as u128) as u64
+        };
+        let settled = self
+            .committed_settled
+            .checked_add(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        Ok(filled_through(settled) - filled_through(self.committed_settled))
+    }
+
+    // An isolated round fails with its sale, or on its own soft cap once over
+    pub fn tranche_failed(&self, sale_status: SaleStatus, now: i64) -> bool {
+        sale_status == SaleStatus::Failed
+            || (now >= self.end_time && self.sol_raised < self.soft_cap)
+    }
+
+    // Inventory open to public purchases, excluding the grant reserve
+    pub fn public_available(&self) -> u64 {
+        self.tokens_available.saturating_sub(self.reserved_tokens)
+    }
+
+    // Tokens an investor with `guaranteed` allowlisted tokens may buy
+    pub fn available_to(&self, guaranteed: u64) -> u64 {
+        self.public_available() + guaranteed.min(self.reserved_tokens)
+    }
+
+    // Per-investor token cap from max_alloc_bps, against everything the round offered
+    pub fn max_allocation(&self) -> u64 {
+        let total = self.tokens_available as u128 + self.tokens_sold as u128;
+        (total * self.max_alloc_bps as u128 / 10_000) as u64
+    }
+
+    pub fn reveal_starts_at(&self) -> i64 {
+        self.end_time.saturating_sub(self.reveal_window)
+    }
+
+    // A zeroed tier list accepts any amount
+    pub fn accepts_tier(&self, amount: u64) -> bool {
+        self.allowed_tiers.iter().all(|&tier| tier == 0)
+            || self
+                .allowed_tiers
+                .iter()
+                .any(|&tier| tier != 0 && tier == amount)
+    }
+
+    // Payment that buys `tokens` at `price_per_token`, rounded down so it never
+    // converts back into more than `tokens`
+    pub fn cost_of(&self, tokens: u64, price_per_token: u64, decimals: u8) -> Result<u64> {
+        u64::try_from(tokens as u128 * price_per_token as u128 / decimal_scale(decimals)?)
+            .map_err(|_| error!(LaunchpadError::MathOverflow))
+    }
+
+    // Per-investor limits on a contribution worth `value` lamports; the minimum
+    // gates entry only, so investors already in may top up by less
+    pub fn check_contribution(&self, value: u64, first_purchase: bool) -> Result<()> {
+        if first_purchase && value < self.min_contribution {
+            msg!(
+                "Contribution {} below minimum {}",
+                value,
+                self.min_contribution
+            );
+            return err!(LaunchpadError::ContributionTooLow);
+        }
+        if value > self.max_contribution {
+            msg!(
+                "Contribution {} above maximum {}",
+                value,
+                self.max_contribution
+            );
+            return err!(LaunchpadError::ContributionExceeded);
+        }
+        Ok(())
+    }
+
+    // SOL the tokens bought with `amount` of a payment token would cost at the round price
+    pub fn sol_value_of(&self, amount: u64, payment_price: u64) -> Result<u64> {
+        u64::try_from(amount as u128 * self.price_per_token as u128 / payment_price as u128)
+            .map_err(|_| error!(LaunchpadError::MathOverflow))
+    }
+
+    // Base units of the token 1 SOL buys at the round price, rounded down
+    pub fn tokens_per_sol(&self, decimals: u8) -> Result<u64> {
+        let scaled = LAMPORTS_PER_SOL as u128 * decimal_scale(decimals)?;
+        u64::try_from(scaled / self.price_per_token as u128)
+            .map_err(|_| error!(LaunchpadError::MathOverflow))
+    }
+
+    // Convert a payment amount into tokens at `price_per_token`, rounding the remainder per `rounding`
+    pub fn tokens_for(
+        &self,
+        amount: u64,
+        price_per_token: u64,
+        decimals: u8,
+        available: u64,
+    ) -> Result<u64> {
+        let scaled = amount as u128 * decimal_scale(decimals)?;
+        let price = price_per_token as u128;
+        let floor = scaled
+            .checked_div(price)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        let remainder = scaled % price;
+
+        require!(available > 0, LaunchpadError::RoundSoldOut);
+        if floor > available as u128 {
+            msg!("Requested {} tokens, {} available", floor, available);
+            return err!(LaunchpadError::InsufficientTokens);
+        }
+        let floor = floor as u64;
+
+        let rounded = match self.rounding {
+            RoundingMode::Down => floor,
+            RoundingMode::Up if remainder > 0 => floor + 1,
+            RoundingMode::Up => floor,
+            // Round half to even so ties don't systematically favor either side
+            RoundingMode::Nearest => match remainder.cmp(&(price - remainder)) {
+                std::cmp::Ordering::Greater => floor + 1,
+                std::cmp::Ordering::Equal => floor + (floor & 1),
+                std::cmp::Ordering::Less => floor,
+            },
+        };
+
+        // Rounding up must never hand out more than the round has left
+        Ok(rounded.min(available))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    Down,
+    Up,
+    Nearest,
 }
 
 #[account]
 pub struct VestingSchedule {
+    pub token_sale: Pubkey,
     pub investor: Pubkey,
     pub total_allocation: u64,
     pub released: u64,
     pub start_time: i64,
     pub duration: u64,
+    pub cliff: u64,
+    pub tge_bps: u16,
+    pub release_interval: u64,
+    pub source: VestingSource,
+    pub price_per_token: u64,
+    pub last_claim_time: i64,
+    // Round the tokens come from, and SOL paid (zero for grants and SPL payments)
+    pub sale_round: Pubkey,
+    pub paid: u64,
+    pub version: u8,
+    pub claim_interval: u64,
+    // Seeds the address was derived from: sale, sale_round, seed_investor, nonce
+    pub seed_investor: Pubkey,
+    pub nonce: u64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum VestingSource {
+    Purchase,
+    Grant,
+}
+
+// One entry per schedule returned by investor_positions
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct PositionSummary {
+    pub sale: Pubkey,
+    pub total_allocation: u64,
+    pub released: u64,
+    pub claimable_now: u64,
+}
+
+// Returned by vesting_stats
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct VestingStats {
+    pub vested: u64,
+    pub locked: u64,
+}
+
+// Everything needed to create a round, shared by single and batched creation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RoundConfig {
+    pub price_per_token: u64,
+    pub tokens_available: u64,
+    pub min_contribution: u64,
+    pub max_contribution: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub rounding: RoundingMode,
+    pub allowed_tiers: [u64; 4],
+    pub vesting: Option<VestingTerms>,
+    pub max_tokens_per_tx: u64,
+    pub partial_fill_allowed: bool,
+}
+
+impl RoundConfig {
+    pub fn validate(&self, launchpad: &Launchpad) -> Result<()> {
+        if let Some(vesting) = &self.vesting {
+            vesting.validate()?;
+            launchpad.check_vesting_duration(vesting.duration)?;
+        }
+        validate_round_times(self.start_time, self.end_time)?;
+        require!(
+            self.price_per_token >= launchpad.min_price_per_token,
+            LaunchpadError::PriceBelowFloor
+        );
+
+        if launchpad.min_round_lead > 0 {
+            let earliest_start = Clock::get()?
+                .unix_timestamp
+                .checked_add(launchpad.min_round_lead)
+                .ok_or(LaunchpadError::MathOverflow)?;
+            require!(
+                self.start_time >= earliest_start,
+                LaunchpadError::InsufficientLeadTime
+            );
+        }
+        Ok(())
+    }
+}
+
+// The default (zero duration) releases everything immediately
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VestingTerms {
+    pub duration: u64,
+    pub cliff: u64,
+    pub tge_bps: u16,
+    pub release_interval: u64,
+    // Claims snap down to multiples of this while accrual stays continuous (0 disables)
+    pub claim_interval: u64,
+}
+
+impl VestingTerms {
+    pub const LEN: usize = 8 + 8 + 2 + 8 + 8;
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.tge_bps <= 10_000 && self.cliff <= self.duration,
+            LaunchpadError::InvalidVestingTerms
+        );
+        Ok(())
+    }
+}
+
+impl VestingSchedule {
+    pub const LEN: usize =
+        32 + 32 + 8 + 8 + 8 + 8 + 8 + 2 + 8 + 1 + 8 + 8 + 32 + 8 + 1 + 8 + 32 + 8 + 1;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &mut self,
+        token_sale: Pubkey,
+        investor: Pubkey,
+        total_allocation: u64,
+        price_per_token: u64,
+        source: VestingSource,
+        terms: &VestingTerms,
+        start_time: i64,
+    ) {
+        self.token_sale = token_sale;
+        self.investor = investor;
+        self.total_allocation = total_allocation;
+        self.released = 0;
+        self.start_time = start_time;
+        self.source = source;
+        self.price_per_token = price_per_token;
+        self.duration = terms.duration;
+        self.cliff = terms.cliff;
+        self.tge_bps = terms.tge_bps;
+        self.release_interval = terms.release_interval;
+        self.claim_interval = terms.claim_interval;
+        self.last_claim_time = 0;
+        self.version = ACCOUNT_VERSION;
+    }
+
+    // Keep the derivation seeds; the investor seed survives transfer_vesting
+    pub fn stamp_address(&mut self, sale_round: Pubkey, nonce: u64, bump: u8) {
+        self.sale_round = sale_round;
+        self.seed_investor = self.investor;
+        self.nonce = nonce;
+        self.bump = bump;
+    }
+
+    // Total amount vested at `now`, including anything already released
+    pub fn vested_at(&self, now: i64) -> Result<u64> {
+        let elapsed = now
+            .checked_sub(self.start_time)
+            .ok_or(LaunchpadError::MathOverflow)?;
+
+        require!(elapsed >= 0, LaunchpadError::VestingNotStarted);
+
+        let elapsed = elapsed as u64;
+        if elapsed >= self.duration {
+            return Ok(self.total_allocation);
+        }
+
+        // Work in u128 so large allocations times bps or seconds can't overflow
+        let total = self.total_allocation as u128;
+
+        // TGE unlocks immediately; the rest streams linearly once past the cliff
+        let tge = total * self.tge_bps as u128 / 10_000;
+        if elapsed < self.cliff {
+            return u64::try_from(tge).map_err(|_| error!(LaunchpadError::MathOverflow));
+        }
+
+        let stepped = match self.release_interval {
+            0 => elapsed,
+            interval => elapsed - elapsed % interval,
+        };
+        let linear = (total - tge) * stepped as u128 / self.duration as u128;
+
+        u64::try_from(tge + linear).map_err(|_| error!(LaunchpadError::MathOverflow))
+    }
+
+    // Never evaluate earlier than the last claim, so a clock that steps backwards
+    // can't shrink what has vested below what was already released
+    pub fn monotonic_time(&self, now: i64) -> i64 {
+        now.max(self.last_claim_time)
+    }
+
+    // Amount claimable at `now`: what had vested at the last claim interval boundary
+    pub fn claimable_vested_at(&self, now: i64) -> Result<u64> {
+        let elapsed = now.saturating_sub(self.start_time);
+        if self.claim_interval == 0 || elapsed < 0 || elapsed as u64 >= self.duration {
+            return self.vested_at(now);
+        }
+
+        let elapsed = elapsed as u64;
+        let snapped = elapsed - elapsed % self.claim_interval;
+        self.vested_at(self.start_time + snapped as i64)
+    }
+}
+
+#[account]
+pub struct Contribution {
+    pub token_sale: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub refunded: bool,
+    pub bump: u8,
+    // Number of vesting schedules created, and the nonce for the next one
+    pub vesting_count: u64,
+    // SOL price per token for this investor, overriding the round's (0 disables)
+    pub price_override: u64,
+    // Tokens bought through purchase_tokens, checked against points caps
+    pub tokens_bought: u64,
+    // Allowlisted tokens still to buy out of round reserves, set by seed_contribution
+    pub guaranteed_allocation: u64,
+    // Lamports paid and tokens received for SOL purchases, for the cost basis
+    pub total_spent: u64,
+    pub total_tokens: u64,
+}
+
+impl Contribution {
+    pub const LEN: usize = 32 + 32 + 8 + 1 + 1 + 8 + 8 + 8 + 8 + 8 + 8;
+
+    pub fn record_spend(&mut self, spent: u64, tokens: u64) -> Result<()> {
+        self.total_spent = self
+            .total_spent
+            .checked_add(spent)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        self.total_tokens = self
+            .total_tokens
+            .checked_add(tokens)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        Ok(())
+    }
+
+    // Lamports per whole token across all SOL purchases, 0 before any
+    pub fn avg_price(&self, decimals: u8) -> Result<u64> {
+        if self.total_tokens == 0 {
+            return Ok(0);
+        }
+        let scaled = (self.total_spent as u128)
+            .checked_mul(decimal_scale(decimals)?)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        u64::try_from(scaled / self.total_tokens as u128)
+            .map_err(|_| error!(LaunchpadError::MathOverflow))
+    }
+
+    // Stamp identity (a no-op after the first time) and advance the vesting nonce
+    pub fn track(&mut self, token_sale: Pubkey, investor: Pubkey, bump: u8) -> Result<()> {
+        self.token_sale = token_sale;
+        self.investor = investor;
+        self.bump = bump;
+        self.vesting_count = self
+            .vesting_count
+            .checked_add(1)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Commitment {
+    pub sale_round: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub settled: bool,
+    pub bump: u8,
+}
+
+impl Commitment {
+    pub fn add(
+        &mut self,
+        sale_round: Pubkey,
+        investor: Pubkey,
+        amount: u64,
+        bump: u8,
+    ) -> Result<()> {
+        self.sale_round = sale_round;
+        self.investor = investor;
+        self.amount = self
+            .amount
+            .checked_add(amount)
+            .ok_or(LaunchpadError::MathOverflow)?;
+        self.bump = bump;
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Bid {
+    pub sale_round: Pubkey,
+    pub investor: Pubkey,
+    pub commitment: [u8; 32],
+    pub revealed: bool,
+    pub bump: u8,
+}
+
+#[account]
+pub struct AllowedMint {
+    pub launchpad: Pubkey,
+    pub mint: Pubkey,
+    pub bump: u8,
+}
+
+// What an investor paid in one accepted SPL token, and its SOL value
+#[account]
+pub struct PaymentPosition {
+    pub token_sale: Pubkey,
+    pub investor: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub value: u64,
+    pub bump: u8,
+}
+
+// An investor's running token total in one round
+#[account]
+pub struct RoundPosition {
+    pub sale_round: Pubkey,
+    pub investor: Pubkey,
+    pub tokens_bought: u64,
+    pub bump: u8,
+}
+
+// Registrant's attestation that an investor is a verified human
+#[account]
+pub struct Verification {
+    pub token_sale: Pubkey,
+    pub investor: Pubkey,
+    pub bump: u8,
+}
+
+#[account]
+pub struct InvestorMarker {
+    pub launchpad: Pubkey,
+    pub investor: Pubkey,
+    pub bump: u8,
 }
@@ -1,16 +1,5191 @@
 import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  Ed25519Program,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+} from "@solana/web3.js";
+import {
+  createAssociatedTokenAccount,
+  createMint,
+  freezeAccount,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  NATIVE_MINT,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import { createHash } from "crypto";
 import { SolanaLaunchpad } from "../target/types/solana_launchpad";
 
 describe("solana-launchpad", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
 
   const program = anchor.workspace.SolanaLaunchpad as Program<SolanaLaunchpad>;
+  const connection = provider.connection;
+  const admin = (provider.wallet as anchor.Wallet).payer;
+  const launchpad = Keypair.generate();
+
+  const airdrop = async (to: PublicKey, sol = 100) => {
+    const sig = await connection.requestAirdrop(to, sol * LAMPORTS_PER_SOL);
+    await connection.confirmTransaction(sig);
+  };
+
+  // Assert that `promise` fails with the Anchor error `code`
+  const expectError = async (promise: Promise<unknown>, code: string) => {
+    let error: any = null;
+    try {
+      await promise;
+    } catch (err) {
+      error = err;
+    }
+    assert.isNotNull(error, `expected ${code}, but the call succeeded`);
+    assert.equal(error.error?.errorCode?.code, code, String(error));
+  };
+
+  type Sale = {
+    tokenSale: Keypair;
+    registrant: Keypair;
+    vault: PublicKey;
+    mint: PublicKey;
+    vaultTokenAccount: PublicKey;
+  };
+
+  const allowedMintPda = (mint: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("allowed_mint"),
+        launchpad.publicKey.toBuffer(),
+        mint.toBuffer(),
+      ],
+      program.programId
+    )[0];
+
+  // Register a sale with a fresh mint and `deposit` tokens moved into its vault
+  const createSale = async ({
+    softCap = new BN(1 * LAMPORTS_PER_SOL),
+    hardCap = new BN(1000 * LAMPORTS_PER_SOL),
+    deposit = new BN(1_000_000).mul(new BN(LAMPORTS_PER_SOL)),
+    finalizationGrace = new BN(0),
+    kycAuthority = PublicKey.default,
+    allowMint = false,
+    freezable = false,
+    termsHash = new Array(32).fill(0),
+    decimals = 9,
+  } = {}): Promise<Sale> => {
+    const registrant = Keypair.generate();
+    const tokenSale = Keypair.generate();
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), tokenSale.publicKey.toBuffer()],
+      program.programId
+    );
+    await airdrop(registrant.publicKey);
+
+    const mint = await createMint(
+      connection,
+      registrant,
+      registrant.publicKey,
+      freezable ? registrant.publicKey : null,
+      decimals
+    );
+    const { address: vaultTokenAccount } =
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        registrant,
+        mint,
+        vault,
+        true
+      );
+    const registrantTokenAccount = await createAssociatedTokenAccount(
+      connection,
+      registrant,
+      mint,
+      registrant.publicKey
+    );
+    await mintTo(
+      connection,
+      registrant,
+      mint,
+      registrantTokenAccount,
+      registrant,
+      BigInt(deposit.toString())
+    );
+
+    let allowedMint: PublicKey | null = null;
+    if (allowMint) {
+      allowedMint = allowedMintPda(mint);
+      await program.methods
+        .allowMint()
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          mint,
+          allowedMint,
+          admin: admin.publicKey,
+        })
+        .rpc();
+    }
+
+    await program.methods
+      .registerToken(
+        softCap,
+        hardCap,
+        mint,
+        finalizationGrace,
+        kycAuthority,
+        termsHash
+      )
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        tokenSale: tokenSale.publicKey,
+        vault,
+        registrant: registrant.publicKey,
+        tokenMint: mint,
+        allowedMint,
+      })
+      .signers([registrant, tokenSale])
+      .rpc();
+
+    await program.methods
+      .depositSaleTokens(deposit)
+      .accountsPartial({
+        tokenSale: tokenSale.publicKey,
+        vault,
+        tokenMint: mint,
+        vaultTokenAccount,
+        registrantTokenAccount,
+        registrant: registrant.publicKey,
+        saleRound: null,
+      })
+      .signers([registrant])
+      .rpc();
+
+    return { tokenSale, registrant, vault, mint, vaultTokenAccount };
+  };
+
+  // 30 days of linear vesting with no cliff or TGE unlock
+  const defaultVesting = {
+    duration: new BN(30 * 86400),
+    cliff: new BN(0),
+    tgeBps: 0,
+    releaseInterval: new BN(0),
+    claimInterval: new BN(0),
+  };
+
+  const addRound = async (
+    sale: Sale,
+    {
+      price = new BN(LAMPORTS_PER_SOL),
+      tokensAvailable = new BN(1_000).mul(new BN(LAMPORTS_PER_SOL)),
+      minContribution = new BN(0),
+      maxContribution = new BN(100 * LAMPORTS_PER_SOL),
+      rounding = { down: {} } as any,
+      allowedTiers = [0, 0, 0, 0],
+      vesting = defaultVesting as typeof defaultVesting | null,
+      maxTokensPerTx = new BN(0),
+      partialFillAllowed = false,
+      activate = true,
+      startTime = Math.floor(Date.now() / 1000) - 60,
+      endTime = Math.floor(Date.now() / 1000) + 3600,
+    } = {}
+  ) => {
+    const saleRound = Keypair.generate();
+    await program.methods
+      .addSaleRound(
+        price,
+        tokensAvailable,
+        minContribution,
+        maxContribution,
+        new BN(startTime),
+        new BN(endTime),
+        rounding,
+        allowedTiers.map((tier) => new BN(tier)),
+        vesting,
+        maxTokensPerTx,
+        partialFillAllowed
+      )
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        tokenSale: sale.tokenSale.publicKey,
+        saleRound: saleRound.publicKey,
+        registrant: sale.registrant.publicKey,
+      })
+      .signers([sale.registrant, saleRound])
+      .rpc();
+    if (activate) await setRoundActive(sale, saleRound, true);
+    return saleRound;
+  };
+
+  const updateRound = (
+    sale: Sale,
+    saleRound: Keypair,
+    {
+      price = new BN(LAMPORTS_PER_SOL),
+      tokensAvailable = new BN(1_000).mul(new BN(LAMPORTS_PER_SOL)),
+      minContribution = new BN(0),
+      maxContribution = new BN(100 * LAMPORTS_PER_SOL),
+      rounding = { down: {} } as any,
+      allowedTiers = [0, 0, 0, 0],
+      vesting = defaultVesting as typeof defaultVesting | null,
+      maxTokensPerTx = new BN(0),
+      partialFillAllowed = false,
+      startTime = Math.floor(Date.now() / 1000) - 60,
+      endTime = Math.floor(Date.now() / 1000) + 3600,
+    } = {}
+  ) =>
+    program.methods
+      .updateSaleRound(
+        price,
+        tokensAvailable,
+        minContribution,
+        maxContribution,
+        new BN(startTime),
+        new BN(endTime),
+        rounding,
+        allowedTiers.map((tier) => new BN(tier)),
+        vesting,
+        maxTokensPerTx,
+        partialFillAllowed
+      )
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        tokenSale: sale.tokenSale.publicKey,
+        saleRound: saleRound.publicKey,
+        registrant: sale.registrant.publicKey,
+      })
+      .signers([sale.registrant])
+      .rpc();
+
+  const setRoundActive = (sale: Sale, saleRound: Keypair, active: boolean) =>
+    (active
+      ? program.methods.activateSaleRound()
+      : program.methods.deactivateSaleRound()
+    )
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        tokenSale: sale.tokenSale.publicKey,
+        saleRound: saleRound.publicKey,
+        registrant: sale.registrant.publicKey,
+      })
+      .signers([sale.registrant])
+      .rpc();
+
+  const newInvestor = async (sale: Sale) => {
+    const investor = Keypair.generate();
+    await airdrop(investor.publicKey);
+    await createAssociatedTokenAccount(
+      connection,
+      investor,
+      sale.mint,
+      investor.publicKey
+    );
+    return investor;
+  };
+
+  const purchase = async (
+    sale: Sale,
+    saleRound: Keypair,
+    investor: Keypair,
+    amount: BN,
+    {
+      preInstructions = [] as TransactionInstruction[],
+      paymentMint = null as PublicKey | null,
+      paymentPosition = null as PublicKey | null,
+      verification = null as PublicKey | null,
+      pointsAccount = null as PublicKey | null,
+      vaultWsolAccount = null as PublicKey | null,
+      termsHash = null as number[] | null,
+      roundPosition = null as PublicKey | null,
+      custody = null as PublicKey | null,
+      roundVault = null as PublicKey | null,
+      ataSponsor = null as Keypair | null,
+    } = {}
+  ) => {
+    const vesting = await nextVestingPda(sale, saleRound, investor.publicKey);
+    const { treasury } = await program.account.launchpad.fetch(
+      launchpad.publicKey
+    );
+    await program.methods
+      .purchaseTokens(amount, termsHash)
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        saleRound: saleRound.publicKey,
+        tokenSale: sale.tokenSale.publicKey,
+        investor: investor.publicKey,
+        treasury,
+        vault: sale.vault,
+        tokenMint: sale.mint,
+        vaultTokenAccount: sale.vaultTokenAccount,
+        investorTokenAccount: getAssociatedTokenAddressSync(
+          sale.mint,
+          investor.publicKey
+        ),
+        vesting,
+        paymentMint,
+        investorPaymentAccount:
+          paymentMint &&
+          getAssociatedTokenAddressSync(paymentMint, investor.publicKey),
+        vaultPaymentAccount:
+          paymentMint &&
+          getAssociatedTokenAddressSync(paymentMint, sale.vault, true),
+        paymentPosition,
+        verification,
+        pointsAccount,
+        vaultWsolAccount,
+        roundPosition,
+        custody,
+        roundVault,
+        ataSponsor: ataSponsor && ataSponsor.publicKey,
+      })
+      .preInstructions(preInstructions)
+      .signers(ataSponsor ? [investor, ataSponsor] : [investor])
+      .rpc();
+    return vesting;
+  };
+
+  const claim = (
+    sale: Sale,
+    investor: Keypair,
+    vesting: PublicKey,
+    {
+      stakeEscrow = null as PublicKey | null,
+      recipient = null as PublicKey | null,
+      amount = null as BN | null,
+    } = {}
+  ) =>
+    program.methods
+      .claimTokens(amount)
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        vesting,
+        tokenSale: sale.tokenSale.publicKey,
+        investor: investor.publicKey,
+        vault: sale.vault,
+        tokenMint: sale.mint,
+        vaultTokenAccount: sale.vaultTokenAccount,
+        investorTokenAccount: getAssociatedTokenAddressSync(
+          sale.mint,
+          investor.publicKey
+        ),
+        stakeEscrow,
+        stakeEscrowTokenAccount:
+          stakeEscrow &&
+          getAssociatedTokenAddressSync(sale.mint, stakeEscrow, true),
+        recipientTokenAccount:
+          recipient && getAssociatedTokenAddressSync(sale.mint, recipient),
+      })
+      .signers([investor])
+      .rpc();
+
+  const tokenBalance = async (sale: Sale, owner: PublicKey) =>
+    (
+      await getAccount(
+        connection,
+        getAssociatedTokenAddressSync(sale.mint, owner, true)
+      )
+    ).amount;
+
+  const sleep = (ms: number) => new Promise((r) => setTimeout(r, ms));
+
+  // Decode the program events emitted by a confirmed transaction
+  const eventsFrom = async (signature: string) => {
+    await connection.confirmTransaction(signature, "confirmed");
+    const tx = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const contributionPda = (sale: Sale, investor: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("contribution"),
+        sale.tokenSale.publicKey.toBuffer(),
+        investor.toBuffer(),
+      ],
+      program.programId
+    )[0];
+
+  const vestingPda = (
+    sale: Sale,
+    round: Keypair,
+    investor: PublicKey,
+    nonce: number
+  ) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting"),
+        sale.tokenSale.publicKey.toBuffer(),
+        round.publicKey.toBuffer(),
+        investor.toBuffer(),
+        new BN(nonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  // Address the next vesting schedule created for an investor will use
+  const nextVestingPda = async (
+    sale: Sale,
+    round: Keypair,
+    investor: PublicKey
+  ) => {
+    const contribution = await program.account.contribution.fetchNullable(
+      contributionPda(sale, investor)
+    );
+    const nonce = contribution ? contribution.vestingCount.toNumber() : 0;
+    return vestingPda(sale, round, investor, nonce);
+  };
+
+  const finalize = (sale: Sale) =>
+    program.methods
+      .finalizeSale()
+      .accountsPartial({ tokenSale: sale.tokenSale.publicKey })
+      .rpc();
+
+  const treasuryOf = async () =>
+    (await program.account.launchpad.fetch(launchpad.publicKey)).treasury;
+
+  const refund = async (sale: Sale, investor: Keypair) =>
+    program.methods
+      .refund()
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        tokenSale: sale.tokenSale.publicKey,
+        contribution: contributionPda(sale, investor.publicKey),
+        vault: sale.vault,
+        investor: investor.publicKey,
+        treasury: await treasuryOf(),
+      })
+      .signers([investor])
+      .rpc();
+
+  const withdrawRaised = (sale: Sale, custody: PublicKey | null = null) =>
+    program.methods
+      .withdrawRaised()
+      .accountsPartial({
+        tokenSale: sale.tokenSale.publicKey,
+        vault: sale.vault,
+        registrant: sale.registrant.publicKey,
+        custody,
+      })
+      .signers([sale.registrant])
+      .rpc();
+
+  // A sale whose only round closes after `secs`, far from its soft cap
+  const createFailingSale = async (secs = 3) => {
+    const sale = await createSale({
+      softCap: new BN(1000 * LAMPORTS_PER_SOL),
+    });
+    const round = await addRound(sale, {
+      endTime: Math.floor(Date.now() / 1000) + secs,
+    });
+    return { sale, round };
+  };
+
+  const setRefundWindow = (sale: Sale, secs: number) =>
+    program.methods
+      .setRefundWindow(new BN(secs))
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        tokenSale: sale.tokenSale.publicKey,
+        registrant: sale.registrant.publicKey,
+      })
+      .signers([sale.registrant])
+      .rpc();
+
+  // Run `fn` with the launchpad's minimum refund window lifted
+  const withoutMinRefundWindow = async (fn: () => Promise<unknown>) => {
+    const setMin = (secs: number) =>
+      program.methods
+        .setMinRefundWindow(new BN(secs))
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+    await setMin(0);
+    try {
+      await fn();
+    } finally {
+      await setMin(7 * 86400);
+    }
+  };
+
+  // Reclaim an abandoned sale; `extra` accounts follow its rounds (isolated
+  // round vaults, then token accounts for each accepted payment mint)
+  const reclaim = (sale: Sale, rounds: Keypair[], extra: PublicKey[] = []) =>
+    program.methods
+      .reclaimAbandoned()
+      .accountsPartial({
+        launchpad: launchpad.publicKey,
+        tokenSale: sale.tokenSale.publicKey,
+        vault: sale.vault,
+        tokenMint: sale.mint,
+        vaultTokenAccount: sale.vaultTokenAccount,
+        registrantTokenAccount: getAssociatedTokenAddressSync(
+          sale.mint,
+          sale.registrant.publicKey
+        ),
+        registrant: sale.registrant.publicKey,
+        treasury: admin.publicKey,
+        admin: admin.publicKey,
+      })
+      .remainingAccounts(
+        [...rounds.map((round) => round.publicKey), ...extra].map(
+          (pubkey) => ({ pubkey, isSigner: false, isWritable: true })
+        )
+      )
+      .rpc();
 
   it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+    const events = await eventsFrom(
+      await program.methods
+        .initialize()
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .signers([launchpad])
+        .rpc()
+    );
+
+    const state = await program.account.launchpad.fetch(launchpad.publicKey);
+    assert.ok(state.admin.equals(admin.publicKey));
+    const changed = events.find((e) => e.name === "adminChanged").data;
+    assert.ok(changed.oldAdmin.equals(PublicKey.default));
+    assert.ok(changed.newAdmin.equals(admin.publicKey));
+  });
+
+  describe("rounding", () => {
+    // 1 SOL at 3 SOL per token leaves a remainder of 1/3 of a token unit
+    const price = new BN(3 * LAMPORTS_PER_SOL);
+    const oneSol = new BN(LAMPORTS_PER_SOL);
+    const twoSol = new BN(2 * LAMPORTS_PER_SOL);
+
+    const allocationFor = async (rounding: any, amount: BN, opts = {}) => {
+      const sale = await createSale();
+      const round = await addRound(sale, { price, rounding, ...opts });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(sale, round, investor, amount);
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      return schedule.totalAllocation.toNumber();
+    };
+
+    it("rounds down by default", async () => {
+      assert.equal(await allocationFor({ down: {} }, oneSol), 333_333_333);
+    });
+
+    it("rounds up in favor of the buyer", async () => {
+      assert.equal(await allocationFor({ up: {} }, oneSol), 333_333_334);
+    });
+
+    it("never rounds up past tokens_available", async () => {
+      const allocation = await allocationFor({ up: {} }, oneSol, {
+        tokensAvailable: new BN(333_333_333),
+      });
+      assert.equal(allocation, 333_333_333);
+    });
+
+    it("rounds to nearest", async () => {
+      assert.equal(await allocationFor({ nearest: {} }, oneSol), 333_333_333);
+      assert.equal(await allocationFor({ nearest: {} }, twoSol), 666_666_667);
+    });
+  });
+
+  describe("update_sale_round", () => {
+    const updatePrice = (sale: Sale, saleRound: Keypair, price: BN) =>
+      updateRound(sale, saleRound, { price });
+
+    it("allows repricing before any sale", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, { activate: false });
+
+      await updatePrice(sale, round, new BN(2 * LAMPORTS_PER_SOL));
+
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(state.pricePerToken.toNumber(), 2 * LAMPORTS_PER_SOL);
+    });
+
+    it("rejects repricing once tokens are sold", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL));
+
+      await expectError(
+        updatePrice(sale, round, new BN(2 * LAMPORTS_PER_SOL)),
+        "RoundHasSales"
+      );
+    });
+  });
+
+  describe("finalize_sale", () => {
+    it("rejects finalization before the grace period elapses", async () => {
+      const sale = await createSale({ finalizationGrace: new BN(3600) });
+      await addRound(sale, { endTime: Math.floor(Date.now() / 1000) - 1 });
+
+      await expectError(finalize(sale), "FinalizationTooEarly");
+    });
+
+    it("finalizes once the sale has ended without a grace period", async () => {
+      const sale = await createSale();
+      await addRound(sale, { endTime: Math.floor(Date.now() / 1000) - 1 });
+
+      await finalize(sale);
+
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(state.status, { failed: {} });
+    });
+  });
+
+  describe("active rounds", () => {
+    const activeRounds = async (sale: Sale) =>
+      (await program.account.tokenSale.fetch(sale.tokenSale.publicKey))
+        .activeRounds;
+
+    it("counts rounds through activation and deactivation", async () => {
+      const sale = await createSale();
+      assert.equal(await activeRounds(sale), 0);
+
+      const first = await addRound(sale);
+      await setRoundActive(sale, first, true);
+      assert.equal(await activeRounds(sale), 1);
+
+      const second = await addRound(sale);
+      assert.equal(await activeRounds(sale), 2);
+
+      await setRoundActive(sale, second, false);
+      await setRoundActive(sale, second, false);
+      assert.equal(await activeRounds(sale), 1);
+    });
+
+    it("lets buyers choose between overlapping rounds", async () => {
+      const sale = await createSale();
+      const strategic = await addRound(sale, {
+        price: new BN(LAMPORTS_PER_SOL / 2),
+      });
+      const publicRound = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      const cheap = await purchase(sale, strategic, investor, amount);
+      const full = await purchase(sale, publicRound, investor, amount);
+
+      const allocation = async (vesting: PublicKey) =>
+        (await program.account.vestingSchedule.fetch(vesting)).totalAllocation
+          .toString();
+      assert.equal(await allocation(cheap), "2000000000");
+      assert.equal(await allocation(full), "1000000000");
+    });
+
+    it("rejects purchases in inactive or closed rounds", async () => {
+      const sale = await createSale();
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const now = Math.floor(Date.now() / 1000);
+
+      const inactive = await addRound(sale, { activate: false });
+      await expectError(
+        purchase(sale, inactive, investor, amount),
+        "RoundNotActive"
+      );
+      const upcoming = await addRound(sale, {
+        startTime: now + 3600,
+        endTime: now + 7200,
+      });
+      await expectError(
+        purchase(sale, upcoming, investor, amount),
+        "RoundNotStarted"
+      );
+      const ended = await addRound(sale, { endTime: now - 1 });
+      await expectError(purchase(sale, ended, investor, amount), "RoundEnded");
+    });
+
+    it("is cleared when the sale is finalized", async () => {
+      const sale = await createSale();
+      await addRound(sale, { endTime: Math.floor(Date.now() / 1000) - 1 });
+
+      await program.methods
+        .finalizeSale()
+        .accountsPartial({ tokenSale: sale.tokenSale.publicKey })
+        .rpc();
+      assert.equal(await activeRounds(sale), 0);
+    });
+  });
+
+  describe("claim_tokens", () => {
+    it("never releases more than the allocation across claims", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(10 * LAMPORTS_PER_SOL)
+      );
+
+      await sleep(2000);
+      await claim(sale, investor, vesting);
+      await sleep(2000);
+      await claim(sale, investor, vesting);
+
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.ok(schedule.released.lte(schedule.totalAllocation));
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        schedule.released.toString()
+      );
+    });
+  });
+
+  describe("kyc", () => {
+    const kycAuthority = Keypair.generate();
+
+    // Ed25519 attestation over sale || investor || expires_at
+    const attestation = (signer: Keypair, sale: Sale, investor: Keypair) => {
+      const expiresAt = Buffer.alloc(8);
+      expiresAt.writeBigInt64LE(BigInt(Math.floor(Date.now() / 1000) + 600));
+      return Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: signer.secretKey,
+        message: Buffer.concat([
+          sale.tokenSale.publicKey.toBuffer(),
+          investor.publicKey.toBuffer(),
+          expiresAt,
+        ]),
+      });
+    };
+
+    it("accepts a purchase attested by the kyc authority", async () => {
+      const sale = await createSale({ kycAuthority: kycAuthority.publicKey });
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+
+      await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL), {
+        preInstructions: [attestation(kycAuthority, sale, investor)],
+      });
+    });
+
+    it("rejects missing or foreign attestations", async () => {
+      const sale = await createSale({ kycAuthority: kycAuthority.publicKey });
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "KycRequired"
+      );
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL), {
+          preInstructions: [attestation(Keypair.generate(), sale, investor)],
+        }),
+        "KycRequired"
+      );
+    });
+  });
+
+  describe("allowed tiers", () => {
+    it("only accepts contributions matching a tier", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        allowedTiers: [1, 5, 10, 0].map((sol) => sol * LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL)),
+        "InvalidTier"
+      );
+      await purchase(sale, round, investor, new BN(5 * LAMPORTS_PER_SOL));
+    });
+  });
+
+  describe("reclaim_abandoned", () => {
+    it("is blocked until the abandonment window has passed", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) - 1,
+      });
+      await finalize(sale);
+
+      await expectError(reclaim(sale, [round]), "ReclaimTooEarly");
+    });
+
+    it("returns tokens and closes the sale's accounts after it", async () => {
+      const { sale, round } = await createFailingSale();
+      await withoutMinRefundWindow(() => setRefundWindow(sale, 3));
+      await sleep(4000);
+      await finalize(sale);
+      await sleep(4000);
+
+      await expectError(reclaim(sale, []), "RoundAccountsMismatch");
+      const registrant = sale.registrant.publicKey;
+      const deposited = await tokenBalance(sale, sale.vault);
+      const before = await tokenBalance(sale, registrant);
+      await reclaim(sale, [round]);
+
+      const returned = (await tokenBalance(sale, registrant)) - before;
+      assert.equal(returned.toString(), deposited.toString());
+      for (const account of [
+        sale.tokenSale.publicKey,
+        sale.vaultTokenAccount,
+        round.publicKey,
+      ]) {
+        assert.isNull(await connection.getAccountInfo(account));
+      }
+    });
+  });
+
+  describe("accepted payments", () => {
+    // Create a 6-decimal stablecoin funded for `investor` with a vault ATA
+    const createStablecoin = async (sale: Sale, investor: Keypair) => {
+      const mint = await createMint(
+        connection,
+        sale.registrant,
+        sale.registrant.publicKey,
+        null,
+        6
+      );
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        sale.registrant,
+        mint,
+        sale.vault,
+        true
+      );
+      const investorAccount = await createAssociatedTokenAccount(
+        connection,
+        investor,
+        mint,
+        investor.publicKey
+      );
+      await mintTo(
+        connection,
+        sale.registrant,
+        mint,
+        investorAccount,
+        sale.registrant,
+        BigInt(1_000_000_000)
+      );
+      return mint;
+    };
+
+    const acceptPayments = (sale: Sale, mint: PublicKey, price: BN) =>
+      program.methods
+        .setAcceptedPayments([{ mint, pricePerToken: price }])
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+    // Open the account tracking what `investor` pays in `mint`
+    const openPaymentPosition = async (
+      sale: Sale,
+      investor: Keypair,
+      mint: PublicKey
+    ) => {
+      const [paymentPosition] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("payment_position"),
+          sale.tokenSale.publicKey.toBuffer(),
+          investor.publicKey.toBuffer(),
+          mint.toBuffer(),
+        ],
+        program.programId
+      );
+      await program.methods
+        .openPaymentPosition()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          paymentMint: mint,
+          paymentPosition,
+          investor: investor.publicKey,
+        })
+        .signers([investor])
+        .rpc();
+      return paymentPosition;
+    };
+
+    const tokenAmount = async (account: PublicKey) =>
+      (await getAccount(connection, account)).amount.toString();
+
+    it("prices tokens per accepted stablecoin", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const usdc = await createStablecoin(sale, investor);
+      const usdt = await createStablecoin(sale, investor);
+      const unsupported = await createStablecoin(sale, investor);
+
+      // 0.5 USDC or 0.25 USDT per whole token
+      await program.methods
+        .setAcceptedPayments([
+          { mint: usdc, pricePerToken: new BN(500_000) },
+          { mint: usdt, pricePerToken: new BN(250_000) },
+        ])
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      const paid = new BN(1_000_000);
+      await expectError(
+        purchase(sale, round, investor, paid, { paymentMint: usdc }),
+        "PaymentPositionRequired"
+      );
+      const withUsdc = await purchase(sale, round, investor, paid, {
+        paymentMint: usdc,
+        paymentPosition: await openPaymentPosition(sale, investor, usdc),
+      });
+      const withUsdt = await purchase(sale, round, investor, paid, {
+        paymentMint: usdt,
+        paymentPosition: await openPaymentPosition(sale, investor, usdt),
+      });
+
+      const allocation = async (vesting: PublicKey) =>
+        (await program.account.vestingSchedule.fetch(vesting)).totalAllocation
+          .toString();
+      assert.equal(await allocation(withUsdc), "2000000000");
+      assert.equal(await allocation(withUsdt), "4000000000");
+      const vaultUsdc = await getAccount(
+        connection,
+        getAssociatedTokenAddressSync(usdc, sale.vault, true)
+      );
+      assert.equal(vaultUsdc.amount.toString(), paid.toString());
+
+      await expectError(
+        purchase(sale, round, investor, paid, { paymentMint: unsupported }),
+        "UnsupportedPaymentMint"
+      );
+    });
+
+    it("applies SOL limits at the stablecoin's SOL value", async () => {
+      const sale = await createSale();
+      // 2 SOL at most, and 1 USDC buys 2 SOL worth of tokens
+      const round = await addRound(sale, {
+        maxContribution: new BN(2 * LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+      const usdc = await createStablecoin(sale, investor);
+      await acceptPayments(sale, usdc, new BN(500_000));
+      const paymentPosition = await openPaymentPosition(sale, investor, usdc);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(1_500_000), {
+          paymentMint: usdc,
+          paymentPosition,
+        }),
+        "ContributionExceeded"
+      );
+      await purchase(sale, round, investor, new BN(1_000_000), {
+        paymentMint: usdc,
+        paymentPosition,
+      });
+      const position = await program.account.paymentPosition.fetch(
+        paymentPosition
+      );
+      assert.equal(position.amount.toString(), "1000000");
+      assert.equal(position.value.toString(), String(2 * LAMPORTS_PER_SOL));
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(
+        state.paymentValueRaised.toString(),
+        String(2 * LAMPORTS_PER_SOL)
+      );
+    });
+
+    it("auto-finalizes when a stablecoin payment fills the cap", async () => {
+      const sale = await createSale({ hardCap: new BN(2 * LAMPORTS_PER_SOL) });
+      await program.methods
+        .setAutoFinalizeOnHardcap(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const usdc = await createStablecoin(sale, investor);
+      await acceptPayments(sale, usdc, new BN(500_000));
+
+      // 1 USDC is worth 2 SOL here, the whole hard cap
+      await purchase(sale, round, investor, new BN(1_000_000), {
+        paymentMint: usdc,
+        paymentPosition: await openPaymentPosition(sale, investor, usdc),
+      });
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(state.status, { succeeded: {} });
+    });
+
+    it("sweeps abandoned stablecoin payments to the treasury", async () => {
+      const { sale, round } = await createFailingSale(15);
+      const investor = await newInvestor(sale);
+      const usdc = await createStablecoin(sale, investor);
+      await acceptPayments(sale, usdc, new BN(500_000));
+      const paid = new BN(1_000_000);
+      await purchase(sale, round, investor, paid, {
+        paymentMint: usdc,
+        paymentPosition: await openPaymentPosition(sale, investor, usdc),
+      });
+      await withoutMinRefundWindow(() => setRefundWindow(sale, 3));
+      const { endTime } = await program.account.saleRound.fetch(
+        round.publicKey
+      );
+      await sleep(endTime.toNumber() * 1000 - Date.now() + 1000);
+      await finalize(sale);
+      await sleep(4000);
+
+      const vaultUsdc = getAssociatedTokenAddressSync(usdc, sale.vault, true);
+      const treasuryUsdc = (
+        await getOrCreateAssociatedTokenAccount(
+          connection,
+          admin,
+          usdc,
+          admin.publicKey
+        )
+      ).address;
+      await expectError(reclaim(sale, [round]), "PaymentAccountsMismatch");
+      await reclaim(sale, [round], [vaultUsdc, treasuryUsdc]);
+
+      assert.equal(await tokenAmount(treasuryUsdc), paid.toString());
+      assert.isNull(await connection.getAccountInfo(vaultUsdc));
+    });
+
+    it("pays stablecoins out to the registrant after success", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      const usdc = await createStablecoin(sale, investor);
+      await acceptPayments(sale, usdc, new BN(500_000));
+      await purchase(sale, round, investor, new BN(1_000_000), {
+        paymentMint: usdc,
+        paymentPosition: await openPaymentPosition(sale, investor, usdc),
+      });
+
+      // Stablecoin value alone meets the 1 SOL soft cap
+      await sleep(4000);
+      await finalize(sale);
+      const destination = await createAssociatedTokenAccount(
+        connection,
+        sale.registrant,
+        usdc,
+        sale.registrant.publicKey
+      );
+      const vaultUsdc = getAssociatedTokenAddressSync(usdc, sale.vault, true);
+      const withdrawPayments = () =>
+        program.methods
+          .withdrawPayments()
+          .accountsPartial({
+            tokenSale: sale.tokenSale.publicKey,
+            vault: sale.vault,
+            paymentMint: usdc,
+            vaultPaymentAccount: vaultUsdc,
+            destination,
+            registrant: sale.registrant.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+      await withdrawPayments();
+      assert.equal(await tokenAmount(destination), "1000000");
+      assert.equal(await tokenAmount(vaultUsdc), "0");
+      await expectError(withdrawPayments(), "NothingToWithdraw");
+    });
+
+    it("refunds stablecoins in kind when the sale fails", async () => {
+      const { sale, round } = await createFailingSale();
+      const investor = await newInvestor(sale);
+      const usdc = await createStablecoin(sale, investor);
+      await acceptPayments(sale, usdc, new BN(500_000));
+      const paymentPosition = await openPaymentPosition(sale, investor, usdc);
+      await purchase(sale, round, investor, new BN(1_000_000), {
+        paymentMint: usdc,
+        paymentPosition,
+      });
+
+      await sleep(4000);
+      await finalize(sale);
+      const investorUsdc = getAssociatedTokenAddressSync(
+        usdc,
+        investor.publicKey
+      );
+      await program.methods
+        .refundPayment()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          vault: sale.vault,
+          paymentMint: usdc,
+          paymentPosition,
+          vaultPaymentAccount: getAssociatedTokenAddressSync(
+            usdc,
+            sale.vault,
+            true
+          ),
+          investorPaymentAccount: investorUsdc,
+          investor: investor.publicKey,
+        })
+        .signers([investor])
+        .rpc();
+      assert.equal(await tokenAmount(investorUsdc), "1000000000");
+      assert.isNull(await connection.getAccountInfo(paymentPosition));
+    });
+  });
+
+  describe("vesting terms", () => {
+    it("snapshots round terms onto the schedule at purchase", async () => {
+      const terms = {
+        duration: new BN(90 * 86400),
+        cliff: new BN(7 * 86400),
+        tgeBps: 1_000,
+        releaseInterval: new BN(86400),
+        claimInterval: new BN(0),
+      };
+      const sale = await createSale();
+      const round = await addRound(sale, { vesting: terms });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      await setRoundActive(sale, round, false);
+      await updateRound(sale, round, { vesting: defaultVesting });
+
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(schedule.duration.toNumber(), 90 * 86400);
+      assert.equal(schedule.cliff.toNumber(), 7 * 86400);
+      assert.equal(schedule.tgeBps, 1_000);
+      assert.equal(schedule.releaseInterval.toNumber(), 86400);
+
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(state.vesting.duration.toNumber(), 30 * 86400);
+    });
+  });
+
+  describe("create_vesting_grant", () => {
+    it("grants from round inventory and vests over time", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, { activate: false });
+      const beneficiary = await newInvestor(sale);
+      const vesting = vestingPda(sale, round, beneficiary.publicKey, 0);
+      const amount = new BN(100 * LAMPORTS_PER_SOL);
+
+      await program.methods
+        .createVestingGrant(
+          beneficiary.publicKey,
+          amount,
+          new BN(4),
+          new BN(0),
+          null
+        )
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vesting,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.deepEqual(schedule.source, { grant: {} });
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(
+        state.tokensAvailable.toString(),
+        new BN(900 * LAMPORTS_PER_SOL).toString()
+      );
+
+      await sleep(2000);
+      await claim(sale, beneficiary, vesting);
+      const partial = await tokenBalance(sale, beneficiary.publicKey);
+      assert.ok(partial > BigInt(0) && partial < BigInt(amount.toString()));
+
+      await sleep(3000);
+      await claim(sale, beneficiary, vesting);
+      assert.equal(
+        (await tokenBalance(sale, beneficiary.publicKey)).toString(),
+        amount.toString()
+      );
+    });
+  });
+
+  describe("refund", () => {
+    it("refunds once and closes the contribution", async () => {
+      const { sale, round } = await createFailingSale();
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+      await sleep(4000);
+      await finalize(sale);
+
+      const before = await connection.getBalance(investor.publicKey);
+      await refund(sale, investor);
+      const after = await connection.getBalance(investor.publicKey);
+      assert.ok(after - before >= 2 * LAMPORTS_PER_SOL);
+      assert.isNull(
+        await connection.getAccountInfo(
+          contributionPda(sale, investor.publicKey)
+        )
+      );
+
+      await expectError(refund(sale, investor), "AccountNotInitialized");
+    });
+  });
+
+  describe("TokensClaimed", () => {
+    it("carries the purchase price and the claimed amount", async () => {
+      const price = new BN(2 * LAMPORTS_PER_SOL);
+      const sale = await createSale();
+      const round = await addRound(sale, { price });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(10 * LAMPORTS_PER_SOL)
+      );
+
+      await sleep(2000);
+      const events = await eventsFrom(await claim(sale, investor, vesting));
+      const event = events.find((e) => e.name === "tokensClaimed");
+
+      assert.equal(event.data.pricePerToken.toString(), price.toString());
+      assert.equal(
+        event.data.amount.toString(),
+        (await tokenBalance(sale, investor.publicKey)).toString()
+      );
+    });
+  });
+
+  describe("zero purchases", () => {
+    it("rejects a zero-amount purchase", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(0)),
+        "ZeroAmount"
+      );
+    });
+  });
+
+  describe("remaining_capacity", () => {
+    it("decreases with each purchase", async () => {
+      const sale = await createSale({ hardCap: new BN(10 * LAMPORTS_PER_SOL) });
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const remaining = () =>
+        program.methods
+          .remainingCapacity()
+          .accountsPartial({ tokenSale: sale.tokenSale.publicKey })
+          .view();
+
+      assert.equal((await remaining()).toNumber(), 10 * LAMPORTS_PER_SOL);
+      await purchase(sale, round, investor, new BN(3 * LAMPORTS_PER_SOL));
+      assert.equal((await remaining()).toNumber(), 7 * LAMPORTS_PER_SOL);
+      await purchase(sale, round, investor, new BN(7 * LAMPORTS_PER_SOL));
+      assert.equal((await remaining()).toNumber(), 0);
+    });
+  });
+
+  describe("sale vault", () => {
+    it("receives purchases and pays out withdrawals", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+      assert.ok(
+        (await connection.getBalance(sale.vault)) >= 2 * LAMPORTS_PER_SOL
+      );
+
+      await sleep(4000);
+      await finalize(sale);
+      const before = await connection.getBalance(sale.registrant.publicKey);
+      await withdrawRaised(sale);
+      const after = await connection.getBalance(sale.registrant.publicKey);
+      assert.ok(after - before >= 2 * LAMPORTS_PER_SOL - 10_000);
+    });
+
+    it("rejects a vault derived for another sale", async () => {
+      const sale = await createSale();
+      const other = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(
+          { ...sale, vault: other.vault },
+          round,
+          investor,
+          new BN(LAMPORTS_PER_SOL)
+        ),
+        "ConstraintSeeds"
+      );
+    });
+  });
+
+  describe("min_round_lead", () => {
+    const setMinRoundLead = (lead: number) =>
+      program.methods
+        .setMinRoundLead(new BN(lead))
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("rejects rounds starting within the lead time", async () => {
+      const sale = await createSale();
+      await setMinRoundLead(3600);
+      try {
+        const now = Math.floor(Date.now() / 1000);
+        await expectError(
+          addRound(sale, { startTime: now + 60, endTime: now + 7200 }),
+          "InsufficientLeadTime"
+        );
+        await addRound(sale, {
+          startTime: now + 7200,
+          endTime: now + 10800,
+          activate: false,
+        });
+      } finally {
+        await setMinRoundLead(0);
+      }
+    });
+
+    it("rejects moving a round's start into the lead time", async () => {
+      const sale = await createSale();
+      const now = Math.floor(Date.now() / 1000);
+      const round = await addRound(sale, {
+        startTime: now + 7200,
+        endTime: now + 10800,
+        activate: false,
+      });
+      await setMinRoundLead(3600);
+      try {
+        await expectError(
+          updateRound(sale, round, {
+            startTime: now + 60,
+            endTime: now + 7200,
+          }),
+          "InsufficientLeadTime"
+        );
+        await updateRound(sale, round, {
+          startTime: now + 7200,
+          endTime: now + 14400,
+        });
+      } finally {
+        await setMinRoundLead(0);
+      }
+    });
+  });
+
+  describe("sweep_vault_dust", () => {
+    it("sweeps dust to the treasury and keeps the vault rent-exempt", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+
+      // Stray lamports sent straight to the vault outside of any purchase
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: admin.publicKey,
+            toPubkey: sale.vault,
+            lamports: LAMPORTS_PER_SOL,
+          })
+        )
+      );
+
+      await sleep(4000);
+      await finalize(sale);
+      await withdrawRaised(sale);
+
+      const treasuryBefore = await connection.getBalance(admin.publicKey);
+      await program.methods
+        .sweepVaultDust()
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          vault: sale.vault,
+          treasury: admin.publicKey,
+          authority: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      const rentExempt = await connection.getMinimumBalanceForRentExemption(0);
+      assert.equal(await connection.getBalance(sale.vault), rentExempt);
+      assert.equal(
+        (await connection.getBalance(admin.publicKey)) - treasuryBefore,
+        LAMPORTS_PER_SOL
+      );
+    });
+  });
+
+  describe("pro-rata allocation", () => {
+    const commit = (
+      sale: Sale,
+      round: Keypair,
+      investor: Keypair,
+      amount: BN
+    ) =>
+      program.methods
+        .commit(amount)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vault: sale.vault,
+          investor: investor.publicKey,
+          verification: null,
+        })
+        .signers([investor])
+        .rpc();
+
+    // Settled by the admin wallet, without the investor's signature
+    const settle = async (sale: Sale, round: Keypair, investor: Keypair) => {
+      const vesting = await nextVestingPda(sale, round, investor.publicKey);
+      await program.methods
+        .finalizeAllocation()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vesting,
+          vault: sale.vault,
+          investor: investor.publicKey,
+          payer: admin.publicKey,
+        })
+        .rpc();
+      return vesting;
+    };
+
+    it("holds commitments to the purchase gates", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        minContribution: new BN(LAMPORTS_PER_SOL),
+        maxContribution: new BN(5 * LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+      const setState = (state: any) =>
+        program.methods
+          .setSaleState(state)
+          .accountsPartial({
+            tokenSale: sale.tokenSale.publicKey,
+            registrant: sale.registrant.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+
+      await expectError(
+        commit(sale, round, investor, new BN(LAMPORTS_PER_SOL / 2)),
+        "ContributionTooLow"
+      );
+      await expectError(
+        commit(sale, round, investor, new BN(6 * LAMPORTS_PER_SOL)),
+        "ContributionExceeded"
+      );
+
+      await setState({ windingDown: {} });
+      await expectError(
+        commit(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "SaleWindingDown"
+      );
+      await setState({ open: {} });
+      await commit(sale, round, investor, new BN(LAMPORTS_PER_SOL));
+    });
+
+    it("fills 50% and refunds the rest at 2x oversubscription", async () => {
+      const sale = await createSale({ hardCap: new BN(10 * LAMPORTS_PER_SOL) });
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 4,
+      });
+      const investors = [await newInvestor(sale), await newInvestor(sale)];
+
+      for (const investor of investors) {
+        await commit(sale, round, investor, new BN(10 * LAMPORTS_PER_SOL));
+      }
+
+      await sleep(5000);
+      for (const investor of investors) {
+        const before = await connection.getBalance(investor.publicKey);
+        const vesting = await settle(sale, round, investor);
+        const after = await connection.getBalance(investor.publicKey);
+
+        // The settler pays rent and fees, so the refund arrives whole
+        assert.equal(after - before, 5 * LAMPORTS_PER_SOL);
+        const schedule = await program.account.vestingSchedule.fetch(vesting);
+        assert.equal(
+          schedule.totalAllocation.toString(),
+          new BN(5 * LAMPORTS_PER_SOL).toString()
+        );
+      }
+
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.totalRaised.toNumber(), 10 * LAMPORTS_PER_SOL);
+    });
+
+    it("fills only what the hard cap has left after purchases", async () => {
+      const sale = await createSale({ hardCap: new BN(10 * LAMPORTS_PER_SOL) });
+      const now = Math.floor(Date.now() / 1000);
+      const round = await addRound(sale, { endTime: now + 4 });
+      const later = await addRound(sale, {
+        startTime: now + 3600,
+        endTime: now + 7200,
+      });
+      const buyer = await newInvestor(sale);
+      const committer = await newInvestor(sale);
+
+      await expectError(
+        commit(sale, later, committer, new BN(LAMPORTS_PER_SOL)),
+        "RoundNotStarted"
+      );
+      await purchase(sale, round, buyer, new BN(4 * LAMPORTS_PER_SOL));
+      await commit(sale, round, committer, new BN(12 * LAMPORTS_PER_SOL));
+
+      await sleep(5000);
+      const before = await connection.getBalance(committer.publicKey);
+      const vesting = await settle(sale, round, committer);
+      const after = await connection.getBalance(committer.publicKey);
+
+      assert.equal(after - before, 6 * LAMPORTS_PER_SOL);
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(
+        schedule.totalAllocation.toString(),
+        new BN(6 * LAMPORTS_PER_SOL).toString()
+      );
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.totalRaised.toNumber(), 10 * LAMPORTS_PER_SOL);
+      assert.equal(state.committedReserved.toNumber(), 0);
+    });
+  });
+
+  describe("curated launchpad", () => {
+    const setCurated = (curated: boolean) =>
+      program.methods
+        .setCurated(curated)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("only registers allowlisted mints", async () => {
+      await setCurated(true);
+      try {
+        await expectError(createSale(), "MintNotAllowed");
+        await createSale({ allowMint: true });
+      } finally {
+        await setCurated(false);
+      }
+    });
+
+    it("activates rounds only once the admin approves the sale", async () => {
+      await setCurated(true);
+      try {
+        const sale = await createSale({ allowMint: true });
+        const round = await addRound(sale, { activate: false });
+        await expectError(
+          setRoundActive(sale, round, true),
+          "SaleNotApproved"
+        );
+
+        await program.methods
+          .approveSale()
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            tokenSale: sale.tokenSale.publicKey,
+            admin: admin.publicKey,
+          })
+          .rpc();
+        await setRoundActive(sale, round, true);
+        const state = await program.account.saleRound.fetch(round.publicKey);
+        assert.isTrue(state.isActive);
+      } finally {
+        await setCurated(false);
+      }
+    });
+  });
+
+  describe("claim cooldown", () => {
+    const setClaimCooldown = (cooldown: number) =>
+      program.methods
+        .setClaimCooldown(new BN(cooldown))
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("rejects rapid repeat claims until the cooldown passes", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(10 * LAMPORTS_PER_SOL)
+      );
+
+      await setClaimCooldown(5);
+      try {
+        await sleep(2000);
+        await claim(sale, investor, vesting);
+        await sleep(1000);
+        await expectError(
+          claim(sale, investor, vesting),
+          "ClaimCooldownActive"
+        );
+        await sleep(5000);
+        await claim(sale, investor, vesting);
+      } finally {
+        await setClaimCooldown(0);
+      }
+    });
+  });
+
+  describe("self purchase", () => {
+    const setBlockSelfPurchase = (block: boolean) =>
+      program.methods
+        .setBlockSelfPurchase(block)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("blocks registrants from buying their own sale", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+
+      await setBlockSelfPurchase(true);
+      try {
+        await expectError(
+          purchase(sale, round, sale.registrant, new BN(LAMPORTS_PER_SOL)),
+          "SelfPurchaseBlocked"
+        );
+      } finally {
+        await setBlockSelfPurchase(false);
+      }
+    });
+  });
+
+  describe("SaleFinalized", () => {
+    const finalizedEvent = async (sale: Sale) => {
+      const events = await eventsFrom(await finalize(sale));
+      return events.find((e) => e.name === "saleFinalized").data;
+    };
+
+    it("reports a successful sale with withdrawals enabled", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+      await sleep(4000);
+
+      const event = await finalizedEvent(sale);
+      assert.deepEqual(event.status, { succeeded: {} });
+      assert.equal(event.totalRaised.toNumber(), 2 * LAMPORTS_PER_SOL);
+      assert.equal(event.softCap.toNumber(), LAMPORTS_PER_SOL);
+      assert.isTrue(event.withdrawalsEnabled);
+      assert.isFalse(event.refundsEnabled);
+    });
+
+    it("reports a failed sale with refunds enabled", async () => {
+      const { sale } = await createFailingSale(1);
+      await sleep(2000);
+
+      const event = await finalizedEvent(sale);
+      assert.deepEqual(event.status, { failed: {} });
+      assert.isFalse(event.withdrawalsEnabled);
+      assert.isTrue(event.refundsEnabled);
+    });
+  });
+
+  describe("vault rent", () => {
+    it("accepts a sub-rent purchase into a freshly registered sale", async () => {
+      const sale = await createSale();
+      const rentExempt = await connection.getMinimumBalanceForRentExemption(0);
+      assert.equal(await connection.getBalance(sale.vault), rentExempt);
+
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(1_000));
+
+      assert.equal(await connection.getBalance(sale.vault), rentExempt + 1_000);
+    });
+  });
+
+  describe("vesting nonces", () => {
+    it("derives every schedule from the contribution count", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      const first = await purchase(sale, round, investor, amount);
+      const second = await purchase(sale, round, investor, amount);
+
+      const contribution = await program.account.contribution.fetch(
+        contributionPda(sale, investor.publicKey)
+      );
+      assert.equal(contribution.vestingCount.toNumber(), 2);
+      assert.ok(first.equals(vestingPda(sale, round, investor.publicKey, 0)));
+      assert.ok(second.equals(vestingPda(sale, round, investor.publicKey, 1)));
+
+      for (const vesting of [first, second]) {
+        const schedule = await program.account.vestingSchedule.fetch(vesting);
+        assert.ok(schedule.investor.equals(investor.publicKey));
+      }
+    });
+  });
+
+  describe("fee config", () => {
+    const setFeeConfig = (feeBps: number, treasury: PublicKey) =>
+      program.methods
+        .setFeeConfig(feeBps, treasury)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("rejects invalid fees and a default treasury", async () => {
+      await expectError(
+        setFeeConfig(10_001, admin.publicKey),
+        "InvalidFeeConfig"
+      );
+      await expectError(
+        setFeeConfig(100, PublicKey.default),
+        "InvalidFeeConfig"
+      );
+    });
+
+    it("applies the new fee and treasury to the next purchase", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const treasury = Keypair.generate().publicKey;
+
+      const events = await eventsFrom(await setFeeConfig(100, treasury));
+      const updated = events.find((e) => e.name === "feeConfigUpdated").data;
+      assert.equal(updated.feeBps, 100);
+      assert.ok(updated.treasury.equals(treasury));
+
+      try {
+        await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL));
+        assert.equal(
+          await connection.getBalance(treasury),
+          LAMPORTS_PER_SOL / 100
+        );
+      } finally {
+        await setFeeConfig(0, admin.publicKey);
+      }
+    });
+  });
+
+  describe("vault underfunded", () => {
+    it("fails claims against an underfunded vault clearly", async () => {
+      // Two rounds each backed by the same single deposited token
+      const oneToken = new BN(LAMPORTS_PER_SOL);
+      const sale = await createSale({ deposit: oneToken });
+      const opts = {
+        tokensAvailable: oneToken,
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      };
+      const first = await addRound(sale, opts);
+      const second = await addRound(sale, opts);
+      const investor = await newInvestor(sale);
+      const paid = await purchase(sale, first, investor, oneToken);
+      const unbacked = await purchase(sale, second, investor, oneToken);
+
+      await claim(sale, investor, paid);
+      await expectError(claim(sale, investor, unbacked), "VaultUnderfunded");
+    });
+  });
+
+  describe("withdraw stream", () => {
+    const withdrawStreamed = (sale: Sale) =>
+      program.methods
+        .withdrawStreamed()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          vault: sale.vault,
+          registrant: sale.registrant.publicKey,
+          custody: null,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+    it("releases raised funds linearly after finalization", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setWithdrawStream(new BN(10))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      const raised = new BN(2 * LAMPORTS_PER_SOL);
+      await purchase(sale, round, investor, raised);
+
+      await sleep(4000);
+      await finalize(sale);
+      await expectError(withdrawRaised(sale), "WithdrawalStreamed");
+
+      const withdrawn = async () =>
+        (await program.account.tokenSale.fetch(sale.tokenSale.publicKey))
+          .withdrawn;
+      await sleep(2000);
+      await withdrawStreamed(sale);
+      const partial = await withdrawn();
+      assert.ok(partial.gtn(0) && partial.lt(raised));
+
+      await sleep(10000);
+      await withdrawStreamed(sale);
+      assert.equal((await withdrawn()).toString(), raised.toString());
+      await expectError(withdrawStreamed(sale), "NothingToWithdraw");
+    });
+  });
+
+  describe("vault inventory", () => {
+    it("blocks activating a round the deposit can't cover", async () => {
+      const sale = await createSale({ deposit: new BN(LAMPORTS_PER_SOL) });
+      const round = await addRound(sale, { activate: false });
+
+      await expectError(
+        setRoundActive(sale, round, true),
+        "InsufficientVaultInventory"
+      );
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.tokensDeposited.toNumber(), LAMPORTS_PER_SOL);
+    });
+  });
+
+  describe("cancel_purchase", () => {
+    const cancel = async (
+      sale: Sale,
+      round: Keypair,
+      investor: Keypair,
+      vesting: PublicKey
+    ) =>
+      program.methods
+        .cancelPurchase()
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vesting,
+          contribution: contributionPda(sale, investor.publicKey),
+          vault: sale.vault,
+          investor: investor.publicKey,
+          treasury: await treasuryOf(),
+        })
+        .signers([investor])
+        .rpc();
+
+    it("refunds inside the cooling-off window only", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setCooloff(new BN(3))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      const cancelled = await purchase(sale, round, investor, amount);
+      await cancel(sale, round, investor, cancelled);
+      assert.isNull(await connection.getAccountInfo(cancelled));
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(state.tokensSold.toNumber(), 0);
+      const sold = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(sold.totalRaised.toNumber(), 0);
+
+      const kept = await purchase(sale, round, investor, amount);
+      await sleep(4000);
+      await expectError(
+        cancel(sale, round, investor, kept),
+        "CooloffExpired"
+      );
+    });
+
+    it("blocks refunded investors unless reentry is allowed", async () => {
+      const sale = await createSale();
+      const registrant = {
+        tokenSale: sale.tokenSale.publicKey,
+        registrant: sale.registrant.publicKey,
+      };
+      await program.methods
+        .setCooloff(new BN(60))
+        .accountsPartial(registrant)
+        .signers([sale.registrant])
+        .rpc();
+      const setReentry = (allowed: boolean) =>
+        program.methods
+          .setReentryAllowed(allowed)
+          .accountsPartial(registrant)
+          .signers([sale.registrant])
+          .rpc();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      await setReentry(false);
+      const vesting = await purchase(sale, round, investor, amount);
+      await cancel(sale, round, investor, vesting);
+      await expectError(
+        purchase(sale, round, investor, amount),
+        "ReentryBlocked"
+      );
+
+      await setReentry(true);
+      await purchase(sale, round, investor, amount);
+      const contribution = await program.account.contribution.fetch(
+        contributionPda(sale, investor.publicKey)
+      );
+      assert.isFalse(contribution.refunded);
+      assert.equal(contribution.amount.toNumber(), LAMPORTS_PER_SOL);
+    });
+  });
+
+  describe("max_tokens_per_tx", () => {
+    it("rejects purchases buying more tokens than the limit", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        maxTokensPerTx: new BN(5 * LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(6 * LAMPORTS_PER_SOL)),
+        "TxTokenLimitExceeded"
+      );
+      await purchase(sale, round, investor, new BN(5 * LAMPORTS_PER_SOL));
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+    });
+  });
+
+  describe("global investor cap", () => {
+    const setMaxTotalInvestors = (max: number) =>
+      program.methods
+        .setMaxTotalInvestors(new BN(max))
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("rejects new investors once the cap is reached", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const existing = await newInvestor(sale);
+      const newcomer = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      await purchase(sale, round, existing, amount);
+
+      const { totalInvestors } = await program.account.launchpad.fetch(
+        launchpad.publicKey
+      );
+      await setMaxTotalInvestors(totalInvestors.toNumber());
+      try {
+        await expectError(
+          purchase(sale, round, newcomer, amount),
+          "InvestorCapReached"
+        );
+        await purchase(sale, round, existing, amount);
+      } finally {
+        await setMaxTotalInvestors(0);
+      }
+    });
+  });
+
+  describe("frozen destination", () => {
+    it("reports a frozen investor account and keeps the schedule", async () => {
+      const sale = await createSale({ freezable: true });
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      await freezeAccount(
+        connection,
+        sale.registrant,
+        getAssociatedTokenAddressSync(sale.mint, investor.publicKey),
+        sale.mint,
+        sale.registrant
+      );
+      await expectError(claim(sale, investor, vesting), "DestinationFrozen");
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(schedule.released.toNumber(), 0);
+    });
+  });
+
+  describe("price floor", () => {
+    const setMinPrice = (price: BN) =>
+      program.methods
+        .setMinPricePerToken(price)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("rejects rounds priced below the floor", async () => {
+      const sale = await createSale();
+      await setMinPrice(new BN(LAMPORTS_PER_SOL / 10));
+      try {
+        await expectError(
+          addRound(sale, { price: new BN(LAMPORTS_PER_SOL / 100) }),
+          "PriceBelowFloor"
+        );
+        await addRound(sale, { price: new BN(LAMPORTS_PER_SOL / 10) });
+      } finally {
+        await setMinPrice(new BN(0));
+      }
+    });
+
+    it("rejects repricing a round below the floor", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, { activate: false });
+      await setMinPrice(new BN(LAMPORTS_PER_SOL / 10));
+      try {
+        await expectError(
+          updateRound(sale, round, { price: new BN(LAMPORTS_PER_SOL / 100) }),
+          "PriceBelowFloor"
+        );
+        await updateRound(sale, round, {
+          price: new BN(LAMPORTS_PER_SOL / 10),
+        });
+      } finally {
+        await setMinPrice(new BN(0));
+      }
+    });
+  });
+
+  describe("round_keys", () => {
+    it("lists every round in creation order", async () => {
+      const sale = await createSale();
+      const rounds = [
+        await addRound(sale),
+        await addRound(sale, { activate: false }),
+        await addRound(sale),
+      ];
+
+      const { roundKeys } = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(
+        roundKeys.map((key) => key.toBase58()),
+        rounds.map((round) => round.publicKey.toBase58())
+      );
+    });
+  });
+
+  describe("transfer_vesting", () => {
+    const transferVesting = (
+      sale: Sale,
+      investor: Keypair,
+      vesting: PublicKey,
+      newBeneficiary: PublicKey
+    ) =>
+      program.methods
+        .transferVesting(newBeneficiary)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          vesting,
+          investor: investor.publicKey,
+        })
+        .signers([investor])
+        .rpc();
+
+    it("moves the unreleased remainder to a new wallet", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, duration: new BN(6) },
+      });
+      const investor = await newInvestor(sale);
+      const newWallet = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const vesting = await purchase(sale, round, investor, amount);
+
+      await expectError(
+        transferVesting(sale, investor, vesting, newWallet.publicKey),
+        "VestingNotTransferable"
+      );
+      await program.methods
+        .setTransferable(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      await sleep(2000);
+      await claim(sale, investor, vesting);
+      const claimed = await tokenBalance(sale, investor.publicKey);
+      assert.ok(claimed > BigInt(0));
+      await transferVesting(sale, investor, vesting, newWallet.publicKey);
+
+      await sleep(6000);
+      await claim(sale, newWallet, vesting);
+      assert.equal(
+        (claimed + (await tokenBalance(sale, newWallet.publicKey))).toString(),
+        amount.toString()
+      );
+      await expectError(claim(sale, investor, vesting), "ConstraintHasOne");
+    });
+  });
+
+  describe("round timestamps", () => {
+    it("rejects negative and far-future times", async () => {
+      const sale = await createSale();
+      const now = Math.floor(Date.now() / 1000);
+
+      await expectError(
+        addRound(sale, { startTime: -1, activate: false }),
+        "InvalidTimestamp"
+      );
+      await expectError(
+        addRound(sale, { endTime: -1, activate: false }),
+        "InvalidTimestamp"
+      );
+      await expectError(
+        addRound(sale, { endTime: now + 50 * 365 * 86400, activate: false }),
+        "InvalidTimestamp"
+      );
+      await addRound(sale, { endTime: now + 365 * 86400, activate: false });
+    });
+  });
+
+  describe("partial fills", () => {
+    it("sells the remainder and charges only for it", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        tokensAvailable: new BN(2 * LAMPORTS_PER_SOL),
+        partialFillAllowed: true,
+      });
+      const investor = await newInvestor(sale);
+
+      const before = await connection.getBalance(sale.vault);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(5 * LAMPORTS_PER_SOL)
+      );
+      assert.equal(
+        (await connection.getBalance(sale.vault)) - before,
+        2 * LAMPORTS_PER_SOL
+      );
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(schedule.totalAllocation.toNumber(), 2 * LAMPORTS_PER_SOL);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "RoundSoldOut"
+      );
+    });
+
+    it("still rejects oversized purchases when disabled", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        tokensAvailable: new BN(2 * LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(5 * LAMPORTS_PER_SOL)),
+        "InsufficientTokens"
+      );
+    });
+  });
+
+  describe("extend_vesting", () => {
+    const extendVesting = (sale: Sale, vesting: PublicKey, days: number) =>
+      program.methods
+        .extendVesting(new BN(days * 86400))
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          vesting,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+    it("only ever lengthens a schedule", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      await expectError(
+        extendVesting(sale, vesting, 10),
+        "VestingNotExtended"
+      );
+      await expectError(
+        extendVesting(sale, vesting, 30),
+        "VestingNotExtended"
+      );
+      await extendVesting(sale, vesting, 60);
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(schedule.duration.toNumber(), 60 * 86400);
+    });
+  });
+
+  describe("deposit deadline", () => {
+    const depositRefund = (sale: Sale, investor: Keypair) =>
+      program.methods
+        .claimFailedDepositRefund()
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          contribution: contributionPda(sale, investor.publicKey),
+          vault: sale.vault,
+          investor: investor.publicKey,
+          treasury: await treasuryOf(),
+        })
+        .signers([investor])
+        .rpc();
+
+    it("refunds investors when sold tokens were never deposited", async () => {
+      // Two rounds backed by one deposited token, so sales outrun deposits
+      const oneToken = new BN(LAMPORTS_PER_SOL);
+      const sale = await createSale({ deposit: oneToken });
+      await program.methods
+        .setDepositDeadline(new BN(1))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const endTime = Math.floor(Date.now() / 1000) + 3;
+      const opts = { tokensAvailable: oneToken, endTime };
+      const first = await addRound(sale, opts);
+      const second = await addRound(sale, opts);
+      const investor = await newInvestor(sale);
+      const backed = await purchase(sale, first, investor, oneToken);
+      await purchase(sale, second, investor, oneToken);
+      await expectError(claim(sale, investor, backed), "ClaimsNotEnabled");
+
+      await sleep(4000);
+      await finalize(sale);
+      await expectError(withdrawRaised(sale), "VaultUnderfunded");
+      // Claims stay shut while the sale can still fail and refund in full
+      await expectError(claim(sale, investor, backed), "VaultUnderfunded");
+
+      await sleep(2000);
+      const before = await connection.getBalance(investor.publicKey);
+      await depositRefund(sale, investor);
+      const after = await connection.getBalance(investor.publicKey);
+      assert.ok(after - before >= 2 * LAMPORTS_PER_SOL);
+
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(state.status, { failed: {} });
+    });
+  });
+
+  describe("large allocations", () => {
+    it("vests exact amounts on a 1e18-token schedule", async () => {
+      const supply = new BN(10).pow(new BN(18));
+      const sale = await createSale({ deposit: supply });
+      // At 1 lamport per token, 1 SOL buys 1e18 base units
+      const round = await addRound(sale, {
+        price: new BN(1),
+        tokensAvailable: supply,
+        vesting: {
+          duration: new BN(60 * 86400),
+          cliff: new BN(30 * 86400),
+          tgeBps: 2_500,
+          releaseInterval: new BN(0),
+          claimInterval: new BN(0),
+        },
+      });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      await claim(sale, investor, vesting);
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        supply.divn(4).toString()
+      );
+    });
+  });
+
+  describe("auto-finalize on hard cap", () => {
+    it("settles the sale on the purchase that fills it", async () => {
+      const sale = await createSale({ hardCap: new BN(2 * LAMPORTS_PER_SOL) });
+      await program.methods
+        .setAutoFinalizeOnHardcap(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+
+      await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL));
+      const open = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(open.status, { active: {} });
+
+      await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL));
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(state.status, { succeeded: {} });
+
+      const before = await connection.getBalance(sale.registrant.publicKey);
+      await withdrawRaised(sale);
+      const after = await connection.getBalance(sale.registrant.publicKey);
+      assert.ok(after - before >= 2 * LAMPORTS_PER_SOL - 10_000);
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "SaleNotActive"
+      );
+    });
+  });
+
+  describe("specific errors", () => {
+    it("tells a sold-out round from an oversized purchase", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        tokensAvailable: new BN(2 * LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(3 * LAMPORTS_PER_SOL)),
+        "InsufficientTokens"
+      );
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "RoundSoldOut"
+      );
+    });
+
+    it("tells a full hard cap from an oversized contribution", async () => {
+      const sale = await createSale({ hardCap: new BN(2 * LAMPORTS_PER_SOL) });
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(3 * LAMPORTS_PER_SOL)),
+        "HardCapExceeded"
+      );
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "HardCapReached"
+      );
+    });
+
+    it("reports contribution limits separately", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        minContribution: new BN(LAMPORTS_PER_SOL),
+        maxContribution: new BN(2 * LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL / 2)),
+        "ContributionTooLow"
+      );
+      await expectError(
+        purchase(sale, round, investor, new BN(3 * LAMPORTS_PER_SOL)),
+        "ContributionExceeded"
+      );
+    });
+  });
+
+  describe("default vesting", () => {
+    it("applies sale defaults unless a round overrides them", async () => {
+      const sale = await createSale();
+      const saleDefault = { ...defaultVesting, duration: new BN(90 * 86400) };
+      await program.methods
+        .setDefaultVesting(saleDefault)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const inheriting = await addRound(sale, { vesting: null });
+      const overriding = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      const duration = async (round: Keypair) => {
+        const vesting = await purchase(sale, round, investor, amount);
+        const schedule = await program.account.vestingSchedule.fetch(vesting);
+        return schedule.duration.toNumber();
+      };
+      assert.equal(await duration(inheriting), 90 * 86400);
+      assert.equal(await duration(overriding), 30 * 86400);
+    });
+  });
+
+  describe("claims after finalize", () => {
+    it("holds claims until the sale is finalized", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setClaimsEnabledAfterFinalize(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      await expectError(claim(sale, investor, vesting), "ClaimsNotEnabled");
+      await sleep(4000);
+      await finalize(sale);
+      await claim(sale, investor, vesting);
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        LAMPORTS_PER_SOL.toString()
+      );
+    });
+  });
+
+  describe("token_hard_cap", () => {
+    it("stops sales at the token cap across rounds", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setTokenHardCap(new BN(3 * LAMPORTS_PER_SOL))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const first = await addRound(sale);
+      const second = await addRound(sale);
+      const investor = await newInvestor(sale);
+
+      await purchase(sale, first, investor, new BN(2 * LAMPORTS_PER_SOL));
+      await expectError(
+        purchase(sale, second, investor, new BN(2 * LAMPORTS_PER_SOL)),
+        "TokenHardCapReached"
+      );
+      await purchase(sale, second, investor, new BN(LAMPORTS_PER_SOL));
+    });
+  });
+
+  describe("sealed bids", () => {
+    const bidHash = (amount: BN, nonce: Buffer, investor: PublicKey) =>
+      Array.from(
+        createHash("sha256")
+          .update(amount.toArrayLike(Buffer, "le", 8))
+          .update(nonce)
+          .update(investor.toBuffer())
+          .digest()
+      );
+
+    const revealBid = (
+      sale: Sale,
+      round: Keypair,
+      investor: Keypair,
+      amount: BN,
+      nonce: Buffer
+    ) =>
+      program.methods
+        .revealBid(amount, Array.from(nonce))
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vault: sale.vault,
+          investor: investor.publicKey,
+          verification: null,
+        })
+        .signers([investor])
+        .rpc();
+
+    const commitBid = (
+      sale: Sale,
+      round: Keypair,
+      investor: Keypair,
+      amount: BN,
+      nonce: Buffer
+    ) =>
+      program.methods
+        .commitBid(bidHash(amount, nonce, investor.publicKey))
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          investor: investor.publicKey,
+          verification: null,
+        })
+        .signers([investor])
+        .rpc();
+
+    it("accepts matching reveals and rejects mismatched ones", async () => {
+      const sale = await createSale();
+      const endTime = Math.floor(Date.now() / 1000) + 12;
+      const round = await addRound(sale, { endTime, activate: false });
+      await program.methods
+        .setRevealWindow(new BN(4))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      await setRoundActive(sale, round, true);
+
+      const investor = await newInvestor(sale);
+      const amount = new BN(2 * LAMPORTS_PER_SOL);
+      const nonce = Keypair.generate().publicKey.toBuffer();
+      await commitBid(sale, round, investor, amount, nonce);
+
+      await expectError(
+        revealBid(sale, round, investor, amount, nonce),
+        "RevealNotOpen"
+      );
+      await sleep((endTime - 4) * 1000 - Date.now() + 1000);
+      await expectError(
+        revealBid(sale, round, investor, amount.addn(1), nonce),
+        "BidMismatch"
+      );
+      await revealBid(sale, round, investor, amount, nonce);
+
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.totalCommitted.toString(), amount.toString());
+    });
+
+    it("rejects reveals once the sale has settled", async () => {
+      const sale = await createSale({ hardCap: new BN(2 * LAMPORTS_PER_SOL) });
+      await program.methods
+        .setAutoFinalizeOnHardcap(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const endTime = Math.floor(Date.now() / 1000) + 12;
+      const round = await addRound(sale, { endTime, activate: false });
+      await program.methods
+        .setRevealWindow(new BN(4))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      await setRoundActive(sale, round, true);
+
+      const bidder = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const nonce = Keypair.generate().publicKey.toBuffer();
+      await commitBid(sale, round, bidder, amount, nonce);
+
+      // A purchase fills the hard cap and settles the sale before reveals open
+      const buyer = await newInvestor(sale);
+      await purchase(sale, round, buyer, new BN(2 * LAMPORTS_PER_SOL));
+      await sleep((endTime - 4) * 1000 - Date.now() + 1000);
+      await expectError(
+        revealBid(sale, round, bidder, amount, nonce),
+        "SaleNotActive"
+      );
+    });
+
+    it("holds bids and reveals to the purchase gates", async () => {
+      const sale = await createSale();
+      const endTime = Math.floor(Date.now() / 1000) + 12;
+      const round = await addRound(sale, {
+        endTime,
+        minContribution: new BN(LAMPORTS_PER_SOL),
+        activate: false,
+      });
+      await program.methods
+        .setRevealWindow(new BN(4))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const setState = (state: any) =>
+        program.methods
+          .setSaleState(state)
+          .accountsPartial({
+            tokenSale: sale.tokenSale.publicKey,
+            registrant: sale.registrant.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+
+      const bidder = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL / 2);
+      const nonce = Keypair.generate().publicKey.toBuffer();
+      await expectError(
+        commitBid(sale, round, bidder, amount, nonce),
+        "RoundNotActive"
+      );
+      await setRoundActive(sale, round, true);
+      await setState({ windingDown: {} });
+      await expectError(
+        commitBid(sale, round, bidder, amount, nonce),
+        "SaleWindingDown"
+      );
+      await setState({ open: {} });
+      await commitBid(sale, round, bidder, amount, nonce);
+
+      await sleep((endTime - 4) * 1000 - Date.now() + 1000);
+      await expectError(
+        revealBid(sale, round, bidder, amount, nonce),
+        "ContributionTooLow"
+      );
+    });
+  });
+
+  describe("account versions", () => {
+    it("stamps new accounts with the current version", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      const versions = [
+        (await program.account.launchpad.fetch(launchpad.publicKey)).version,
+        (await program.account.tokenSale.fetch(sale.tokenSale.publicKey))
+          .version,
+        (await program.account.saleRound.fetch(round.publicKey)).version,
+        (await program.account.vestingSchedule.fetch(vesting)).version,
+      ];
+      assert.deepEqual(versions, [1, 1, 1, 1]);
+    });
+  });
+
+  describe("round extensions", () => {
+    const configure = (sale: Sale, round: Keypair) => ({
+      tokenSale: sale.tokenSale.publicKey,
+      saleRound: round.publicKey,
+      registrant: sale.registrant.publicKey,
+    });
+
+    it("stops extending once the cumulative cap is hit", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const extend = (secs: number) =>
+        program.methods
+          .extendRound(new BN(secs))
+          .accountsPartial(configure(sale, round))
+          .signers([sale.registrant])
+          .rpc();
+
+      await expectError(extend(60), "ExtensionLimitReached");
+      await program.methods
+        .setMaxTotalExtension(new BN(3600))
+        .accountsPartial(configure(sale, round))
+        .signers([sale.registrant])
+        .rpc();
+
+      const before = await program.account.saleRound.fetch(round.publicKey);
+      await extend(1800);
+      await extend(1800);
+      await expectError(extend(1), "ExtensionLimitReached");
+
+      const after = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(after.endTime.sub(before.endTime).toNumber(), 3600);
+      assert.equal(after.totalExtended.toNumber(), 3600);
+    });
+  });
+
+  describe("claim-and-stake", () => {
+    it("routes claimed tokens into a staking escrow", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const vesting = await purchase(sale, round, investor, amount);
+
+      const [escrow] = PublicKey.findProgramAddressSync(
+        [Buffer.from("stake"), investor.publicKey.toBuffer()],
+        program.programId
+      );
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        investor,
+        sale.mint,
+        escrow,
+        true
+      );
+
+      await sleep(2000);
+      const events = await eventsFrom(
+        await claim(sale, investor, vesting, { stakeEscrow: escrow })
+      );
+      const event = events.find((e) => e.name === "claimedToStake");
+
+      assert.ok(event.data.stakeEscrow.equals(escrow));
+      assert.equal(
+        event.data.amount.toString(),
+        (await tokenBalance(sale, escrow)).toString()
+      );
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        "0"
+      );
+
+      // Only the investor can move staked tokens back out
+      const unstake = (signer: Keypair, amount: BN) =>
+        program.methods
+          .unstake(amount)
+          .accountsPartial({
+            investor: signer.publicKey,
+            stakeEscrow: escrow,
+            tokenMint: sale.mint,
+            stakeEscrowTokenAccount: getAssociatedTokenAddressSync(
+              sale.mint,
+              escrow,
+              true
+            ),
+            investorTokenAccount: getAssociatedTokenAddressSync(
+              sale.mint,
+              signer.publicKey
+            ),
+          })
+          .signers([signer])
+          .rpc();
+      const other = await newInvestor(sale);
+      await expectError(unstake(other, event.data.amount), "ConstraintSeeds");
+
+      await unstake(investor, event.data.amount);
+      assert.equal((await tokenBalance(sale, escrow)).toString(), "0");
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        event.data.amount.toString()
+      );
+    });
+
+    it("rejects an escrow that isn't the investor's stake PDA", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+      const escrow = Keypair.generate().publicKey;
+      await getOrCreateAssociatedTokenAccount(
+        connection,
+        investor,
+        sale.mint,
+        escrow
+      );
+
+      await sleep(2000);
+      await expectError(
+        claim(sale, investor, vesting, { stakeEscrow: escrow }),
+        "ConstraintSeeds"
+      );
+    });
+  });
+
+  describe("sentinel mints", () => {
+    const register = async (mint: PublicKey) => {
+      const registrant = Keypair.generate();
+      const tokenSale = Keypair.generate();
+      await airdrop(registrant.publicKey);
+      return program.methods
+        .registerToken(
+          new BN(LAMPORTS_PER_SOL),
+          new BN(10 * LAMPORTS_PER_SOL),
+          mint,
+          new BN(0),
+          PublicKey.default,
+          new Array(32).fill(0)
+        )
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: tokenSale.publicKey,
+          vault: PublicKey.findProgramAddressSync(
+            [Buffer.from("vault"), tokenSale.publicKey.toBuffer()],
+            program.programId
+          )[0],
+          registrant: registrant.publicKey,
+          tokenMint: mint,
+          allowedMint: null,
+        })
+        .signers([registrant, tokenSale])
+        .rpc();
+    };
+
+    it("rejects the default pubkey as the sale mint", async () => {
+      let error: any = null;
+      try {
+        await register(PublicKey.default);
+      } catch (err) {
+        error = err;
+      }
+      assert.isNotNull(error, "expected the default mint to be rejected");
+      assert.match(
+        String(error),
+        /InvalidMint|AccountOwnedByWrongProgram|AccountNotInitialized/
+      );
+    });
+
+    it("rejects the native SOL mint as the sale mint", async () => {
+      await expectError(register(NATIVE_MINT), "InvalidMint");
+    });
+  });
+
+  describe("audit_sale", () => {
+    const audit = async (sale: Sale, vestings: PublicKey[]) => {
+      const { roundKeys } = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      return program.methods
+        .auditSale()
+        .accountsPartial({ tokenSale: sale.tokenSale.publicKey })
+        .remainingAccounts(
+          [...roundKeys, ...vestings].map((pubkey) => ({
+            pubkey,
+            isSigner: false,
+            isWritable: false,
+          }))
+        )
+        .view();
+    };
+
+    it("passes a correctly operating sale", async () => {
+      const sale = await createSale();
+      const first = await addRound(sale);
+      const second = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const vestings = [
+        await purchase(sale, first, investor, new BN(LAMPORTS_PER_SOL)),
+        await purchase(sale, second, investor, new BN(LAMPORTS_PER_SOL)),
+      ];
+
+      assert.isTrue(await audit(sale, vestings));
+    });
+
+    it("fails a sale that sold more than was deposited", async () => {
+      // Both rounds are backed by the same single deposited token
+      const oneToken = new BN(LAMPORTS_PER_SOL);
+      const sale = await createSale({ deposit: oneToken });
+      const first = await addRound(sale, { tokensAvailable: oneToken });
+      const second = await addRound(sale, { tokensAvailable: oneToken });
+      const investor = await newInvestor(sale);
+      await purchase(sale, first, investor, oneToken);
+      await purchase(sale, second, investor, oneToken);
+
+      assert.isFalse(await audit(sale, []));
+    });
+
+    it("rejects an incomplete round list", async () => {
+      const sale = await createSale();
+      await addRound(sale);
+      await expectError(
+        program.methods
+          .auditSale()
+          .accountsPartial({ tokenSale: sale.tokenSale.publicKey })
+          .view(),
+        "InvalidAuditAccounts"
+      );
+    });
+  });
+
+  describe("add_sale_rounds", () => {
+    const roundConfig = (overrides = {}) => ({
+      pricePerToken: new BN(LAMPORTS_PER_SOL),
+      tokensAvailable: new BN(1_000).mul(new BN(LAMPORTS_PER_SOL)),
+      minContribution: new BN(0),
+      maxContribution: new BN(100 * LAMPORTS_PER_SOL),
+      startTime: new BN(Math.floor(Date.now() / 1000) - 60),
+      endTime: new BN(Math.floor(Date.now() / 1000) + 3600),
+      rounding: { down: {} },
+      allowedTiers: [0, 0, 0, 0].map((tier) => new BN(tier)),
+      vesting: defaultVesting,
+      maxTokensPerTx: new BN(0),
+      partialFillAllowed: false,
+      ...overrides,
+    });
+
+    const addRounds = (
+      sale: Sale,
+      configs: ReturnType<typeof roundConfig>[]
+    ) => {
+      const rounds = configs.map(() => Keypair.generate());
+      const tx = program.methods
+        .addSaleRounds(configs)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .remainingAccounts(
+          rounds.map((round) => ({
+            pubkey: round.publicKey,
+            isSigner: true,
+            isWritable: true,
+          }))
+        )
+        .signers([sale.registrant, ...rounds])
+        .rpc();
+      return { rounds, tx };
+    };
+
+    it("creates several rounds in one transaction", async () => {
+      const sale = await createSale();
+      const { rounds, tx } = addRounds(sale, [
+        roundConfig(),
+        roundConfig({ pricePerToken: new BN(2 * LAMPORTS_PER_SOL) }),
+        roundConfig({ pricePerToken: new BN(3 * LAMPORTS_PER_SOL) }),
+      ]);
+      await tx;
+
+      const { roundKeys } = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(
+        roundKeys.map((key) => key.toBase58()),
+        rounds.map((round) => round.publicKey.toBase58())
+      );
+      const third = await program.account.saleRound.fetch(
+        rounds[2].publicKey
+      );
+      assert.equal(third.pricePerToken.toNumber(), 3 * LAMPORTS_PER_SOL);
+      assert.ok(third.tokenSale.equals(sale.tokenSale.publicKey));
+    });
+
+    it("reverts the whole batch when one config is invalid", async () => {
+      const sale = await createSale();
+      const { rounds, tx } = addRounds(sale, [
+        roundConfig(),
+        roundConfig(),
+        roundConfig({ startTime: new BN(0) }),
+      ]);
+      await expectError(tx, "InvalidTimestamp");
+
+      const { roundKeys } = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(roundKeys.length, 0);
+      assert.isNull(await connection.getAccountInfo(rounds[0].publicKey));
+    });
+
+    it("rejects an oversized batch before creating rounds", async () => {
+      const sale = await createSale();
+      const configs = Array.from({ length: 5 }, () => roundConfig());
+      const rounds = configs.map(() => Keypair.generate());
+      // Non-signing round keys keep the oversized batch within tx size limits
+      await expectError(
+        program.methods
+          .addSaleRounds(configs)
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            tokenSale: sale.tokenSale.publicKey,
+            registrant: sale.registrant.publicKey,
+          })
+          .remainingAccounts(
+            rounds.map((round) => ({
+              pubkey: round.publicKey,
+              isSigner: false,
+              isWritable: true,
+            }))
+          )
+          .signers([sale.registrant])
+          .rpc(),
+        "BatchTooLarge"
+      );
+
+      const { roundKeys } = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(roundKeys.length, 0);
+    });
+  });
+
+  describe("soft cap extension", () => {
+    it("extends a below-soft-cap sale once instead of failing it", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setSoftcapExtension(new BN(3))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      await addRound(sale, { endTime: Math.floor(Date.now() / 1000) + 2 });
+
+      await sleep(3000);
+      const events = await eventsFrom(await finalize(sale));
+      const extended = events.find((e) => e.name === "saleExtended");
+      let state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.ok(extended);
+      assert.deepEqual(state.status, { active: {} });
+      assert.isTrue(state.softcapExtended);
+      await expectError(finalize(sale), "FinalizationTooEarly");
+
+      await sleep(4000);
+      await finalize(sale);
+      state = await program.account.tokenSale.fetch(sale.tokenSale.publicKey);
+      assert.deepEqual(state.status, { failed: {} });
+    });
+
+    it("keeps the round selling through the extension", async () => {
+      const sale = await createSale({
+        softCap: new BN(2 * LAMPORTS_PER_SOL),
+      });
+      await program.methods
+        .setSoftcapExtension(new BN(6))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 2,
+      });
+      const investor = await newInvestor(sale);
+
+      await sleep(3000);
+      await expectError(
+        purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL)),
+        "RoundEnded"
+      );
+      await finalize(sale);
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+
+      await sleep(7000);
+      await finalize(sale);
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(state.status, { succeeded: {} });
+    });
+  });
+
+  describe("price overrides", () => {
+    it("sells to a strategic investor at their override price", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, { price: new BN(LAMPORTS_PER_SOL) });
+      const strategic = await newInvestor(sale);
+      const regular = await newInvestor(sale);
+      await program.methods
+        .setPriceOverride(strategic.publicKey, new BN(LAMPORTS_PER_SOL / 2))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          contribution: contributionPda(sale, strategic.publicKey),
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const discounted = await program.account.vestingSchedule.fetch(
+        await purchase(sale, round, strategic, amount)
+      );
+      const [{ signature }] = await connection.getSignaturesForAddress(
+        strategic.publicKey,
+        { limit: 1 },
+        "confirmed"
+      );
+      const event = (await eventsFrom(signature)).find(
+        (e) => e.name === "tokensPurchased"
+      );
+      const standard = await program.account.vestingSchedule.fetch(
+        await purchase(sale, round, regular, amount)
+      );
+
+      assert.equal(event.data.pricePerToken.toNumber(), LAMPORTS_PER_SOL / 2);
+      assert.equal(
+        discounted.totalAllocation.toString(),
+        standard.totalAllocation.muln(2).toString()
+      );
+    });
+  });
+
+  describe("reserved tokens", () => {
+    it("keeps the reserve from public buyers but not grants", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        tokensAvailable: new BN(10 * LAMPORTS_PER_SOL),
+      });
+      await program.methods
+        .setReservedTokens(new BN(4 * LAMPORTS_PER_SOL))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(7 * LAMPORTS_PER_SOL)),
+        "InsufficientTokens"
+      );
+      await purchase(sale, round, investor, new BN(6 * LAMPORTS_PER_SOL));
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "RoundSoldOut"
+      );
+
+      const beneficiary = await newInvestor(sale);
+      await program.methods
+        .createVestingGrant(
+          beneficiary.publicKey,
+          new BN(4 * LAMPORTS_PER_SOL),
+          new BN(4),
+          new BN(0),
+          null
+        )
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vesting: vestingPda(sale, round, beneficiary.publicKey, 0),
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(state.tokensAvailable.toNumber(), 0);
+      assert.equal(state.reservedTokens.toNumber(), 0);
+    });
+  });
+
+  describe("investor verification", () => {
+    const verificationPda = (sale: Sale, investor: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("verification"),
+          sale.tokenSale.publicKey.toBuffer(),
+          investor.toBuffer(),
+        ],
+        program.programId
+      )[0];
+
+    it("gates purchases on a registrant attestation", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setRequireVerification(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      await expectError(
+        purchase(sale, round, investor, amount),
+        "InvestorNotVerified"
+      );
+
+      const verification = verificationPda(sale, investor.publicKey);
+      await program.methods
+        .verifyInvestor(investor.publicKey)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          verification,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      await purchase(sale, round, investor, amount, { verification });
+
+      const contribution = await program.account.contribution.fetch(
+        contributionPda(sale, investor.publicKey)
+      );
+      assert.equal(contribution.amount.toString(), amount.toString());
+    });
+  });
+
+  describe("points-weighted caps", () => {
+    it("lets investors with more points buy more", async () => {
+      const sale = await createSale();
+      const pointsMint = await createMint(
+        connection,
+        sale.registrant,
+        sale.registrant.publicKey,
+        null,
+        0
+      );
+      await program.methods
+        .setPointsWeighting(pointsMint, new BN(LAMPORTS_PER_SOL))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale);
+
+      const withPoints = async (points: number) => {
+        const investor = await newInvestor(sale);
+        const pointsAccount = await createAssociatedTokenAccount(
+          connection,
+          investor,
+          pointsMint,
+          investor.publicKey
+        );
+        await mintTo(
+          connection,
+          sale.registrant,
+          pointsMint,
+          pointsAccount,
+          sale.registrant,
+          points
+        );
+        return { investor, pointsAccount };
+      };
+      const small = await withPoints(10);
+      const large = await withPoints(20);
+      const buy = ({ investor, pointsAccount }, sol: number) =>
+        purchase(sale, round, investor, new BN(sol * LAMPORTS_PER_SOL), {
+          pointsAccount,
+        });
+
+      await expectError(
+        purchase(sale, round, small.investor, new BN(LAMPORTS_PER_SOL)),
+        "PointsAccountRequired"
+      );
+      await buy(small, 10);
+      await expectError(buy(small, 1), "PointsCapExceeded");
+      await buy(large, 20);
+      await expectError(buy(large, 1), "PointsCapExceeded");
+    });
+  });
+
+  describe("close_round", () => {
+    const closeRound = (sale: Sale, round: Keypair) =>
+      program.methods
+        .closeRound()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+    it("returns a round's rent once the sale is finalized", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL));
+
+      await expectError(closeRound(sale, round), "SaleNotFinalized");
+      await sleep(4000);
+      await finalize(sale);
+
+      const rent = await connection.getBalance(round.publicKey);
+      const before = await connection.getBalance(sale.registrant.publicKey);
+      await closeRound(sale, round);
+      const after = await connection.getBalance(sale.registrant.publicKey);
+
+      assert.isNull(await connection.getAccountInfo(round.publicKey));
+      // Only the transaction fee is lost from the returned rent
+      assert.isAbove(after - before, rent - 10_000);
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.roundKeys.length, 0);
+      assert.equal(state.closedRoundsRaised.toNumber(), LAMPORTS_PER_SOL);
+    });
+  });
+
+  describe("refund fee", () => {
+    const setRefundFee = (bps: number) =>
+      program.methods
+        .setRefundFeeBps(bps)
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          admin: admin.publicKey,
+        })
+        .rpc();
+
+    it("rejects a fee above 100%", async () => {
+      await expectError(setRefundFee(10_001), "InvalidFeeConfig");
+    });
+
+    it("splits a refund between investor and treasury", async () => {
+      const { sale, round } = await createFailingSale();
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      await purchase(sale, round, investor, amount);
+      await sleep(4000);
+      await finalize(sale);
+
+      // A fresh treasury so transaction fees paid by the admin don't interfere
+      const treasury = Keypair.generate().publicKey;
+      const setTreasury = (to: PublicKey) =>
+        program.methods
+          .setFeeConfig(0, to)
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            admin: admin.publicKey,
+          })
+          .rpc();
+      await setTreasury(treasury);
+      await setRefundFee(500);
+      try {
+        const contributionRent = await connection.getBalance(
+          contributionPda(sale, investor.publicKey)
+        );
+        const before = await connection.getBalance(investor.publicKey);
+        await refund(sale, investor);
+        const fee = amount.toNumber() / 20;
+
+        assert.equal(await connection.getBalance(treasury), fee);
+        assert.equal(
+          (await connection.getBalance(investor.publicKey)) - before,
+          amount.toNumber() - fee + contributionRent
+        );
+      } finally {
+        await setRefundFee(0);
+        await setTreasury(admin.publicKey);
+      }
+    });
+  });
+
+  describe("transfer_registrant", () => {
+    it("hands round management to the accepted registrant", async () => {
+      const sale = await createSale();
+      const newRegistrant = Keypair.generate();
+      const stranger = Keypair.generate();
+      await airdrop(newRegistrant.publicKey);
+      await airdrop(stranger.publicKey);
+
+      await program.methods
+        .transferRegistrant(newRegistrant.publicKey)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const accept = (signer: Keypair) =>
+        program.methods
+          .acceptRegistrant()
+          .accountsPartial({
+            tokenSale: sale.tokenSale.publicKey,
+            newRegistrant: signer.publicKey,
+          })
+          .signers([signer])
+          .rpc();
+      await expectError(accept(stranger), "NotPendingRegistrant");
+      await accept(newRegistrant);
+
+      // The old registrant no longer passes registrant checks
+      await expectError(addRound(sale), "ConstraintHasOne");
+      const round = await addRound({ ...sale, registrant: newRegistrant });
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.isTrue(state.isActive);
+      const { registrant, pendingRegistrant } =
+        await program.account.tokenSale.fetch(sale.tokenSale.publicKey);
+      assert.ok(registrant.equals(newRegistrant.publicKey));
+      assert.ok(pendingRegistrant.equals(PublicKey.default));
+    });
+  });
+
+  describe("claim destination policy", () => {
+    const setSelfOnly = (sale: Sale, selfOnly: boolean) =>
+      program.methods
+        .setClaimToSelfOnly(selfOnly)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+    it("rejects recipient overrides only under self-only", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      });
+      const investor = await newInvestor(sale);
+      const recipient = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const vesting = await purchase(sale, round, investor, amount);
+
+      await setSelfOnly(sale, true);
+      await expectError(
+        claim(sale, investor, vesting, { recipient: recipient.publicKey }),
+        "ClaimToSelfOnly"
+      );
+
+      await setSelfOnly(sale, false);
+      await claim(sale, investor, vesting, { recipient: recipient.publicKey });
+      assert.equal(
+        (await tokenBalance(sale, recipient.publicKey)).toString(),
+        amount.toString()
+      );
+    });
+  });
+
+  describe("admin audit events", () => {
+    const asNumber = (value: any) =>
+      typeof value === "boolean"
+        ? Number(value)
+        : BN.isBN(value)
+        ? value.toNumber()
+        : value;
+
+    const settings = [
+      ["refundFeeBps", (v: any) => program.methods.setRefundFeeBps(v), 250],
+      [
+        "minPricePerToken",
+        (v: any) => program.methods.setMinPricePerToken(v),
+        new BN(5),
+      ],
+      [
+        "maxTotalInvestors",
+        (v: any) => program.methods.setMaxTotalInvestors(v),
+        new BN(1_000_000),
+      ],
+      [
+        "blockSelfPurchase",
+        (v: any) => program.methods.setBlockSelfPurchase(v),
+        true,
+      ],
+      [
+        "claimCooldown",
+        (v: any) => program.methods.setClaimCooldown(v),
+        new BN(7),
+      ],
+      ["curated", (v: any) => program.methods.setCurated(v), true],
+      [
+        "minRoundLead",
+        (v: any) => program.methods.setMinRoundLead(v),
+        new BN(9),
+      ],
+    ] as const;
+
+    for (const [setting, method, next] of settings) {
+      it(`reports old and new ${setting}`, async () => {
+        const update = async (value: any) => {
+          const signature = await method(value)
+            .accountsPartial({
+              launchpad: launchpad.publicKey,
+              admin: admin.publicKey,
+            })
+            .rpc();
+          const events = await eventsFrom(signature);
+          return events.find((e) => e.name === "launchpadSettingChanged").data;
+        };
+        const state = await program.account.launchpad.fetch(
+          launchpad.publicKey
+        );
+        const previous = state[setting];
+
+        const changed = await update(next);
+        assert.deepEqual(changed.setting, { [setting]: {} });
+        assert.equal(changed.oldValue.toNumber(), asNumber(previous));
+        assert.equal(changed.newValue.toNumber(), asNumber(next));
+
+        const restored = await update(previous);
+        assert.equal(restored.oldValue.toNumber(), asNumber(next));
+        assert.equal(restored.newValue.toNumber(), asNumber(previous));
+      });
+    }
+
+    it("reports the previous fee config", async () => {
+      const treasury = Keypair.generate().publicKey;
+      const setFeeConfig = (feeBps: number, to: PublicKey) =>
+        program.methods
+          .setFeeConfig(feeBps, to)
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            admin: admin.publicKey,
+          })
+          .rpc();
+
+      const events = await eventsFrom(await setFeeConfig(75, treasury));
+      await setFeeConfig(0, admin.publicKey);
+      const updated = events.find((e) => e.name === "feeConfigUpdated").data;
+      assert.equal(updated.oldFeeBps, 0);
+      assert.ok(updated.oldTreasury.equals(admin.publicKey));
+      assert.equal(updated.feeBps, 75);
+      assert.ok(updated.treasury.equals(treasury));
+    });
+
+    it("reports allowlist additions and removals", async () => {
+      const mint = await createMint(connection, admin, admin.publicKey, null, 9);
+      const allowedMint = allowedMintPda(mint);
+      const allowed = await eventsFrom(
+        await program.methods
+          .allowMint()
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            mint,
+            allowedMint,
+            admin: admin.publicKey,
+          })
+          .rpc()
+      );
+      const revoked = await eventsFrom(
+        await program.methods
+          .revokeMint()
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            allowedMint,
+            admin: admin.publicKey,
+          })
+          .rpc()
+      );
+
+      for (const [events, expected] of [
+        [allowed, true],
+        [revoked, false],
+      ] as const) {
+        const event = events.find((e) => e.name === "whitelistUpdated").data;
+        assert.ok(event.mint.equals(mint));
+        assert.equal(event.allowed, expected);
+      }
+    });
+
+    it("reports sales pausing and reopening", async () => {
+      const sale = await createSale();
+      const setState = async (state: any) => {
+        const events = await eventsFrom(
+          await program.methods
+            .setSaleState(state)
+            .accountsPartial({
+              tokenSale: sale.tokenSale.publicKey,
+              registrant: sale.registrant.publicKey,
+            })
+            .signers([sale.registrant])
+            .rpc()
+        );
+        return events.find((e) => e.name === "pauseToggled")?.data;
+      };
+
+      const paused = await setState({ windingDown: {} });
+      const reopened = await setState({ open: {} });
+      assert.isTrue(paused.paused);
+      assert.isFalse(reopened.paused);
+      assert.isUndefined(await setState({ open: {} }));
+    });
+  });
+
+  describe("vesting_stats", () => {
+    const vestingStats = (sale: Sale, vestings: PublicKey[]) =>
+      program.methods
+        .vestingStats()
+        .accountsPartial({ tokenSale: sale.tokenSale.publicKey })
+        .remainingAccounts(
+          vestings.map((pubkey) => ({
+            pubkey,
+            isSigner: false,
+            isWritable: false,
+          }))
+        )
+        .view();
+
+    it("sums vested and locked tokens across investors", async () => {
+      const sale = await createSale();
+      // Half unlocks at TGE, the rest over 30 days
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, tgeBps: 5_000 },
+      });
+      const vestings = [];
+      for (const sol of [1, 2, 3]) {
+        const investor = await newInvestor(sale);
+        vestings.push(
+          await purchase(sale, round, investor, new BN(sol * LAMPORTS_PER_SOL))
+        );
+      }
+
+      const stats = await vestingStats(sale, vestings);
+      const total = 6 * LAMPORTS_PER_SOL;
+      assert.equal(stats.vested.add(stats.locked).toNumber(), total);
+      assert.isAtLeast(stats.vested.toNumber(), total / 2);
+      assert.isBelow(stats.vested.toNumber(), total * 0.51);
+    });
+
+    it("rejects schedules from another sale", async () => {
+      const sale = await createSale();
+      const other = await createSale();
+      const round = await addRound(other);
+      const investor = await newInvestor(other);
+      const vesting = await purchase(
+        other,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      await expectError(vestingStats(sale, [vesting]), "ScheduleNotInSale");
+    });
+  });
+
+  describe("wrapped SOL", () => {
+    it("accrues contributions as wSOL in the vault", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setWrapSol(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const { address: vaultWsolAccount } =
+        await getOrCreateAssociatedTokenAccount(
+          connection,
+          sale.registrant,
+          NATIVE_MINT,
+          sale.vault,
+          true
+        );
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      const amount = new BN(2 * LAMPORTS_PER_SOL);
+
+      await expectError(
+        purchase(sale, round, investor, amount),
+        "WsolAccountRequired"
+      );
+      await purchase(sale, round, investor, amount, { vaultWsolAccount });
+      assert.equal(
+        (await getAccount(connection, vaultWsolAccount)).amount.toString(),
+        amount.toString()
+      );
+
+      // Once finalized, unwrapping moves the SOL back into the vault
+      await sleep(4000);
+      await finalize(sale);
+      const before = await connection.getBalance(sale.vault);
+      await program.methods
+        .unwrapVaultSol()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          vault: sale.vault,
+          vaultWsolAccount,
+        })
+        .rpc();
+      assert.isAtLeast(
+        (await connection.getBalance(sale.vault)) - before,
+        amount.toNumber()
+      );
+    });
+  });
+
+  describe("total_raised on refund", () => {
+    it("nets refunds out of total_raised", async () => {
+      const { sale, round } = await createFailingSale();
+      const first = await newInvestor(sale);
+      const second = await newInvestor(sale);
+      await purchase(sale, round, first, new BN(LAMPORTS_PER_SOL));
+      await purchase(sale, round, second, new BN(2 * LAMPORTS_PER_SOL));
+      await sleep(4000);
+      await finalize(sale);
+      const raised = async () =>
+        (await program.account.tokenSale.fetch(sale.tokenSale.publicKey))
+          .totalRaised;
+
+      assert.equal((await raised()).toNumber(), 3 * LAMPORTS_PER_SOL);
+      await refund(sale, first);
+      assert.equal((await raised()).toNumber(), 2 * LAMPORTS_PER_SOL);
+      await refund(sale, second);
+      assert.equal((await raised()).toNumber(), 0);
+
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.totalRefunded.toNumber(), 3 * LAMPORTS_PER_SOL);
+    });
+  });
+
+  describe("admin timelock", () => {
+    const [pendingAction] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_action"), launchpad.publicKey.toBuffer()],
+      program.programId
+    );
+    const accounts = {
+      launchpad: launchpad.publicKey,
+      pendingAction,
+      admin: admin.publicKey,
+    };
+    const queue = (action: any) =>
+      program.methods.queueAction(action).accountsPartial(accounts).rpc();
+    const execute = () =>
+      program.methods.executeAction().accountsPartial(accounts).rpc();
+    const cancel = () =>
+      program.methods.cancelAction().accountsPartial(accounts).rpc();
+    const feeBps = async () =>
+      (await program.account.launchpad.fetch(launchpad.publicKey)).feeBps;
+
+    it("applies a queued fee increase only after the delay", async () => {
+      const treasury = await treasuryOf();
+      await program.methods
+        .setTimelockDelay(new BN(2))
+        .accountsPartial({ launchpad: launchpad.publicKey })
+        .rpc();
+
+      try {
+        await expectError(
+          program.methods
+            .setTimelockDelay(new BN(1))
+            .accountsPartial({ launchpad: launchpad.publicKey })
+            .rpc(),
+          "TimelockDelayShortened"
+        );
+        await expectError(
+          program.methods
+            .setFeeConfig(100, treasury)
+            .accountsPartial({ launchpad: launchpad.publicKey })
+            .rpc(),
+          "FeeIncreaseTimelocked"
+        );
+        await expectError(
+          program.methods
+            .setFeeConfig(0, Keypair.generate().publicKey)
+            .accountsPartial({ launchpad: launchpad.publicKey })
+            .rpc(),
+          "TreasuryChangeTimelocked"
+        );
+
+        await queue({ setFeeConfig: { feeBps: 100, treasury } });
+        await expectError(execute(), "TimelockNotElapsed");
+        assert.equal(await feeBps(), 0);
+
+        await sleep(3000);
+        await execute();
+        assert.equal(await feeBps(), 100);
+        assert.isNull(await connection.getAccountInfo(pendingAction));
+      } finally {
+        // Decreases apply directly; the delay itself has to be queued down
+        await program.methods
+          .setFeeConfig(0, treasury)
+          .accountsPartial({ launchpad: launchpad.publicKey })
+          .rpc();
+        await queue({ setTimelockDelay: { timelockDelay: new BN(0) } });
+        await sleep(3000);
+        await execute();
+      }
+    });
+
+    it("reports a cancelled action without applying it", async () => {
+      const treasury = await treasuryOf();
+      await queue({ setFeeConfig: { feeBps: 100, treasury } });
+      const events = await eventsFrom(await cancel());
+
+      const cancelled = events.find((e) => e.name === "actionCancelled").data;
+      assert.ok(cancelled.launchpad.equals(launchpad.publicKey));
+      assert.equal(cancelled.action.setFeeConfig.feeBps, 100);
+      assert.equal(await feeBps(), 0);
+      assert.isNull(await connection.getAccountInfo(pendingAction));
+    });
+  });
+
+  describe("first-purchase minimum", () => {
+    it("applies min_contribution only to an investor's first buy", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        minContribution: new BN(LAMPORTS_PER_SOL),
+      });
+      const investor = await newInvestor(sale);
+
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL / 2)),
+        "ContributionTooLow"
+      );
+      await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL));
+      await purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL / 10));
+
+      const contribution = await program.account.contribution.fetch(
+        contributionPda(sale, investor.publicKey)
+      );
+      assert.equal(
+        contribution.amount.toNumber(),
+        LAMPORTS_PER_SOL + LAMPORTS_PER_SOL / 10
+      );
+    });
+  });
+
+  describe("claim intervals", () => {
+    it("accrues continuously but snaps claims to intervals", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: {
+          ...defaultVesting,
+          duration: new BN(40),
+          claimInterval: new BN(10),
+        },
+      });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(4 * LAMPORTS_PER_SOL)
+      );
+
+      await sleep(3000);
+      const stats = await program.methods
+        .vestingStats()
+        .accountsPartial({ tokenSale: sale.tokenSale.publicKey })
+        .remainingAccounts([
+          { pubkey: vesting, isSigner: false, isWritable: false },
+        ])
+        .view();
+      assert.isAbove(stats.vested.toNumber(), 0);
+      await expectError(claim(sale, investor, vesting), "NothingToClaim");
+
+      // Past the first boundary a quarter is claimable, not the full accrual
+      await sleep(9000);
+      await claim(sale, investor, vesting);
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(
+        schedule.released.toString(),
+        schedule.totalAllocation.divn(4).toString()
+      );
+    });
+  });
+
+  describe("investor_positions", () => {
+    const positions = (investor: PublicKey, vestings: PublicKey[]) =>
+      program.methods
+        .investorPositions()
+        .accountsPartial({ investor })
+        .remainingAccounts(
+          vestings.map((pubkey) => ({
+            pubkey,
+            isSigner: false,
+            isWritable: false,
+          }))
+        )
+        .view();
+
+    it("summarizes an investor's schedules across sales", async () => {
+      const first = await createSale();
+      const second = await createSale();
+      const investor = await newInvestor(first);
+      const vestings = [
+        await purchase(
+          first,
+          await addRound(first),
+          investor,
+          new BN(LAMPORTS_PER_SOL)
+        ),
+        await purchase(
+          second,
+          await addRound(second, {
+            vesting: { ...defaultVesting, tgeBps: 10_000 },
+          }),
+          investor,
+          new BN(2 * LAMPORTS_PER_SOL)
+        ),
+      ];
+
+      const [a, b] = await positions(investor.publicKey, vestings);
+      assert.ok(a.sale.equals(first.tokenSale.publicKey));
+      assert.equal(a.totalAllocation.toNumber(), LAMPORTS_PER_SOL);
+      assert.equal(a.released.toNumber(), 0);
+      assert.ok(b.sale.equals(second.tokenSale.publicKey));
+      assert.equal(b.totalAllocation.toNumber(), 2 * LAMPORTS_PER_SOL);
+      assert.equal(b.claimableNow.toNumber(), 2 * LAMPORTS_PER_SOL);
+    });
+
+    it("rejects schedules owned by someone else", async () => {
+      const sale = await createSale();
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        await addRound(sale),
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      await expectError(
+        positions(Keypair.generate().publicKey, [vesting]),
+        "ScheduleNotOwned"
+      );
+    });
+  });
+
+  describe("terms hash", () => {
+    it("rejects a mismatched terms hash when enforced", async () => {
+      const termsHash = Array.from(
+        createHash("sha256").update("sale terms v1").digest()
+      );
+      const sale = await createSale({ termsHash });
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      // Not enforced yet, so buyers needn't pass anything
+      await purchase(sale, round, investor, amount);
+
+      await program.methods
+        .setEnforceTermsHash(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      await expectError(
+        purchase(sale, round, investor, amount),
+        "TermsHashMismatch"
+      );
+      await expectError(
+        purchase(sale, round, investor, amount, {
+          termsHash: new Array(32).fill(1),
+        }),
+        "TermsHashMismatch"
+      );
+      await purchase(sale, round, investor, amount, { termsHash });
+    });
+  });
+
+  describe("max_vesting_duration", () => {
+    const setMaxVestingDuration = (duration: number) =>
+      program.methods
+        .setMaxVestingDuration(new BN(duration))
+        .accountsPartial({ launchpad: launchpad.publicKey })
+        .rpc();
+
+    it("rejects rounds vesting longer than the cap", async () => {
+      const sale = await createSale();
+      await setMaxVestingDuration(365 * 86400);
+      try {
+        await expectError(
+          addRound(sale, {
+            vesting: { ...defaultVesting, duration: new BN(366 * 86400) },
+          }),
+          "VestingTooLong"
+        );
+        await addRound(sale, {
+          vesting: { ...defaultVesting, duration: new BN(365 * 86400) },
+        });
+      } finally {
+        await setMaxVestingDuration(0);
+      }
+    });
+
+    it("rejects round updates and sale defaults past the cap", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, { activate: false });
+      const setDefaultVesting = (days: number) =>
+        program.methods
+          .setDefaultVesting({
+            ...defaultVesting,
+            duration: new BN(days * 86400),
+          })
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            tokenSale: sale.tokenSale.publicKey,
+            registrant: sale.registrant.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+      const updateVesting = (days: number) =>
+        updateRound(sale, round, {
+          vesting: { ...defaultVesting, duration: new BN(days * 86400) },
+        });
+
+      await setMaxVestingDuration(365 * 86400);
+      try {
+        await expectError(setDefaultVesting(366), "VestingTooLong");
+        await expectError(updateVesting(366), "VestingTooLong");
+        await setDefaultVesting(365);
+        await updateVesting(365);
+      } finally {
+        await setMaxVestingDuration(0);
+      }
+    });
+
+    it("rejects extending a schedule past the cap", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+      const extendVesting = (days: number) =>
+        program.methods
+          .extendVesting(new BN(days * 86400))
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            tokenSale: sale.tokenSale.publicKey,
+            vesting,
+            registrant: sale.registrant.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+
+      await setMaxVestingDuration(45 * 86400);
+      try {
+        await expectError(extendVesting(60), "VestingTooLong");
+        await extendVesting(45);
+      } finally {
+        await setMaxVestingDuration(0);
+      }
+    });
+  });
+
+  describe("monotonic vesting clock", () => {
+    // The local validator's clock can't be stepped back, so this checks the
+    // invariant the clamp guarantees: vested never dips below a prior reading
+    it("never reports less vested than already released", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, duration: new BN(60) },
+      });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+      const vested = async () => {
+        const [position] = await program.methods
+          .investorPositions()
+          .accountsPartial({ investor: investor.publicKey })
+          .remainingAccounts([
+            { pubkey: vesting, isSigner: false, isWritable: false },
+          ])
+          .view();
+        return position.released.add(position.claimableNow).toNumber();
+      };
+
+      let last = 0;
+      for (let i = 0; i < 3; i++) {
+        await sleep(2000);
+        await claim(sale, investor, vesting);
+        const current = await vested();
+        const schedule = await program.account.vestingSchedule.fetch(vesting);
+        assert.isAtLeast(current, schedule.released.toNumber());
+        assert.isAtLeast(current, last);
+        last = current;
+      }
+    });
+  });
+
+  describe("display_rate", () => {
+    it("matches the tokens a 1-SOL purchase receives", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        price: new BN(LAMPORTS_PER_SOL / 4),
+      });
+      const investor = await newInvestor(sale);
+
+      const rate = await program.methods
+        .displayRate()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+        })
+        .view();
+      assert.equal(rate.toString(), new BN(4 * LAMPORTS_PER_SOL).toString());
+
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(schedule.totalAllocation.toString(), rate.toString());
+    });
+  });
+
+  describe("rounds on finalized sales", () => {
+    it("adds rounds before finalization but not after", async () => {
+      const { sale } = await createFailingSale();
+      const endTime = Math.floor(Date.now() / 1000) + 2;
+      await addRound(sale, { endTime });
+
+      await sleep(4000);
+      await finalize(sale);
+      await expectError(
+        addRound(sale, { endTime: endTime + 3600 }),
+        "SaleFinalized"
+      );
+    });
+  });
+
+  describe("percentage allocation cap", () => {
+    it("caps each investor at a share of the round's tokens", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        tokensAvailable: new BN(100 * LAMPORTS_PER_SOL),
+      });
+      await program.methods
+        .setMaxAllocBps(1_000)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const investor = await newInvestor(sale);
+      const [roundPosition] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("round_position"),
+          round.publicKey.toBuffer(),
+          investor.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "RoundPositionRequired"
+      );
+      await program.methods
+        .openRoundPosition()
+        .accountsPartial({
+          saleRound: round.publicKey,
+          roundPosition,
+          investor: investor.publicKey,
+        })
+        .signers([investor])
+        .rpc();
+
+      // 10% of 100 tokens, reached across two purchases
+      await purchase(sale, round, investor, new BN(6 * LAMPORTS_PER_SOL), {
+        roundPosition,
+      });
+      await purchase(sale, round, investor, new BN(4 * LAMPORTS_PER_SOL), {
+        roundPosition,
+      });
+      await expectError(
+        purchase(sale, round, investor, new BN(LAMPORTS_PER_SOL), {
+          roundPosition,
+        }),
+        "AllocationCapExceeded"
+      );
+    });
+  });
+
+  describe("seed_contribution", () => {
+    it("lets an allowlisted investor buy their guaranteed tokens", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        tokensAvailable: new BN(10 * LAMPORTS_PER_SOL),
+      });
+      const registrant = {
+        tokenSale: sale.tokenSale.publicKey,
+        saleRound: round.publicKey,
+        registrant: sale.registrant.publicKey,
+      };
+      await program.methods
+        .setReservedTokens(new BN(4 * LAMPORTS_PER_SOL))
+        .accountsPartial(registrant)
+        .signers([sale.registrant])
+        .rpc();
+      const allowlisted = await newInvestor(sale);
+      await program.methods
+        .seedContribution(
+          allowlisted.publicKey,
+          new BN(4 * LAMPORTS_PER_SOL),
+          new BN(0)
+        )
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          contribution: contributionPda(sale, allowlisted.publicKey),
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      // The public sells out, leaving only the reserve
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(6 * LAMPORTS_PER_SOL));
+      await purchase(sale, round, allowlisted, new BN(4 * LAMPORTS_PER_SOL));
+
+      const contribution = await program.account.contribution.fetch(
+        contributionPda(sale, allowlisted.publicKey)
+      );
+      assert.equal(contribution.guaranteedAllocation.toNumber(), 0);
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(state.reservedTokens.toNumber(), 0);
+      assert.equal(state.tokensAvailable.toNumber(), 0);
+    });
+  });
+
+  describe("min_tokens_sold", () => {
+    const finalizeWith = async (criteria: any) => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      await program.methods
+        .setMinTokensSold(new BN(5 * LAMPORTS_PER_SOL), criteria)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      // Clears the 1 SOL soft cap but sells only 2 of the 5 tokens
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(2 * LAMPORTS_PER_SOL));
+      await sleep(4000);
+      await finalize(sale);
+      return (await program.account.tokenSale.fetch(sale.tokenSale.publicKey))
+        .status;
+    };
+
+    it("fails a sale short of the token threshold", async () => {
+      assert.deepEqual(await finalizeWith({ all: {} }), { failed: {} });
+    });
+
+    it("succeeds on either target when configured to", async () => {
+      assert.deepEqual(await finalizeWith({ any: {} }), { succeeded: {} });
+    });
+  });
+
+  describe("mint decimals", () => {
+    it("prices 18-decimal mints without overflowing", async () => {
+      const wholeToken = new BN(10).pow(new BN(18));
+      const sale = await createSale({
+        decimals: 18,
+        deposit: wholeToken.muln(10),
+      });
+      const round = await addRound(sale, {
+        tokensAvailable: wholeToken.muln(10),
+      });
+      const investor = await newInvestor(sale);
+
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL / 2)
+      );
+      const schedule = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(
+        schedule.totalAllocation.toString(),
+        wholeToken.divn(2).toString()
+      );
+    });
+
+    it("rejects mints above 18 decimals", async () => {
+      await expectError(
+        createSale({ decimals: 19, deposit: new BN(0) }),
+        "UnsupportedDecimals"
+      );
+    });
+  });
+
+  describe("custody authority", () => {
+    const setCustody = (sale: Sale, custody: PublicKey, direct: boolean) =>
+      program.methods
+        .setCustody(custody, direct)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+    it("pays withdrawals to the custody account", async () => {
+      const sale = await createSale();
+      const custody = Keypair.generate().publicKey;
+      await setCustody(sale, custody, false);
+      const round = await addRound(sale, {
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      const amount = new BN(2 * LAMPORTS_PER_SOL);
+      await purchase(sale, round, investor, amount);
+      await sleep(4000);
+      await finalize(sale);
+
+      await expectError(withdrawRaised(sale), "InvalidCustody");
+      await withdrawRaised(sale, custody);
+      assert.equal(await connection.getBalance(custody), amount.toNumber());
+    });
+
+    it("routes purchases straight to custody when direct", async () => {
+      const sale = await createSale();
+      const custody = Keypair.generate().publicKey;
+      await setCustody(sale, custody, true);
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(2 * LAMPORTS_PER_SOL);
+
+      const vaultBefore = await connection.getBalance(sale.vault);
+      await purchase(sale, round, investor, amount, { custody });
+      assert.equal(await connection.getBalance(custody), amount.toNumber());
+      assert.equal(await connection.getBalance(sale.vault), vaultBefore);
+    });
+  });
+
+  describe("RoundActivated", () => {
+    it("snapshots the round's terms on activation", async () => {
+      const sale = await createSale();
+      const startTime = Math.floor(Date.now() / 1000) - 60;
+      const endTime = startTime + 7200;
+      const round = await addRound(sale, {
+        price: new BN(LAMPORTS_PER_SOL / 2),
+        tokensAvailable: new BN(500 * LAMPORTS_PER_SOL),
+        startTime,
+        endTime,
+        activate: false,
+      });
+
+      const events = await eventsFrom(await setRoundActive(sale, round, true));
+      const activated = events.find((e) => e.name === "roundActivated").data;
+      assert.ok(activated.round.equals(round.publicKey));
+      assert.equal(
+        activated.tokensAvailable.toString(),
+        new BN(500 * LAMPORTS_PER_SOL).toString()
+      );
+      assert.equal(activated.price.toNumber(), LAMPORTS_PER_SOL / 2);
+      assert.equal(activated.start.toNumber(), startTime);
+      assert.equal(activated.end.toNumber(), endTime);
+    });
+  });
+
+  describe("reclaim_unsold", () => {
+    const reclaimUnsold = (sale: Sale, round: Keypair, toRegistrant: boolean) =>
+      program.methods
+        .reclaimUnsold(toRegistrant)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vault: sale.vault,
+          tokenMint: sale.mint,
+          vaultTokenAccount: sale.vaultTokenAccount,
+          registrantTokenAccount: getAssociatedTokenAddressSync(
+            sale.mint,
+            sale.registrant.publicKey
+          ),
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+    it("returns an ended round's unsold tokens to the registrant", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        tokensAvailable: new BN(10 * LAMPORTS_PER_SOL),
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, new BN(4 * LAMPORTS_PER_SOL));
+      await expectError(reclaimUnsold(sale, round, true), "RoundNotEnded");
+
+      const before = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      await sleep(4000);
+      await expectError(reclaimUnsold(sale, round, true), "RoundActive");
+      await setRoundActive(sale, round, false);
+      await reclaimUnsold(sale, round, true);
+
+      const unsold = 6 * LAMPORTS_PER_SOL;
+      const state = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(state.tokensAvailable.toNumber(), 0);
+      assert.isFalse(state.isActive);
+      const after = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(
+        before.tokensDeposited.sub(after.tokensDeposited).toNumber(),
+        unsold
+      );
+      assert.equal(
+        (await tokenBalance(sale, sale.registrant.publicKey)).toString(),
+        unsold.toString()
+      );
+    });
+
+    it("keeps granted tokens in the vault", async () => {
+      const tokens = new BN(10 * LAMPORTS_PER_SOL);
+      const sale = await createSale({ deposit: tokens });
+      const granted = await addRound(sale, {
+        tokensAvailable: tokens,
+        activate: false,
+      });
+      const beneficiary = await newInvestor(sale);
+      await program.methods
+        .createVestingGrant(
+          beneficiary.publicKey,
+          tokens,
+          new BN(4),
+          new BN(0),
+          null
+        )
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: granted.publicKey,
+          vesting: vestingPda(sale, granted, beneficiary.publicKey, 0),
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+
+      // The deposit backs this round too, but it's all owed to the grant
+      const round = await addRound(sale, {
+        tokensAvailable: tokens,
+        endTime: Math.floor(Date.now() / 1000) + 3,
+      });
+      await sleep(4000);
+      await setRoundActive(sale, round, false);
+      await expectError(reclaimUnsold(sale, round, true), "VaultUnderfunded");
+      assert.equal(
+        (await tokenBalance(sale, sale.vault)).toString(),
+        tokens.toString()
+      );
+    });
+  });
+
+  describe("grant start time", () => {
+    it("rejects grants vesting from before the sale existed", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, { activate: false });
+      const beneficiary = await newInvestor(sale);
+      const { createdAt } = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      const grant = (startTime: BN) =>
+        program.methods
+          .createVestingGrant(
+            beneficiary.publicKey,
+            new BN(LAMPORTS_PER_SOL),
+            new BN(86400),
+            new BN(0),
+            startTime
+          )
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            tokenSale: sale.tokenSale.publicKey,
+            saleRound: round.publicKey,
+            vesting: vestingPda(sale, round, beneficiary.publicKey, 0),
+            registrant: sale.registrant.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+
+      // Backdated a full duration, this would otherwise vest instantly
+      await expectError(
+        grant(createdAt.subn(86400)),
+        "InvalidVestingStart"
+      );
+      await grant(createdAt);
+      const schedule = await program.account.vestingSchedule.fetch(
+        vestingPda(sale, round, beneficiary.publicKey, 0)
+      );
+      assert.equal(schedule.startTime.toString(), createdAt.toString());
+    });
+  });
+
+  describe("winding down", () => {
+    it("blocks purchases but not claims or refunds", async () => {
+      const sale = await createSale();
+      const registrant = {
+        tokenSale: sale.tokenSale.publicKey,
+        registrant: sale.registrant.publicKey,
+      };
+      await program.methods
+        .setCooloff(new BN(60))
+        .accountsPartial(registrant)
+        .signers([sale.registrant])
+        .rpc();
+      const round = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const claimed = await purchase(sale, round, investor, amount);
+      const refunded = await purchase(sale, round, investor, amount);
+
+      await program.methods
+        .setSaleState({ windingDown: {} })
+        .accountsPartial(registrant)
+        .signers([sale.registrant])
+        .rpc();
+      await expectError(
+        purchase(sale, round, investor, amount),
+        "SaleWindingDown"
+      );
+
+      await sleep(2000);
+      await claim(sale, investor, claimed);
+      assert.ok((await tokenBalance(sale, investor.publicKey)) > BigInt(0));
+
+      await program.methods
+        .cancelPurchase()
+        .accountsPartial({
+          launchpad: launchpad.publicKey,
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          vesting: refunded,
+          contribution: contributionPda(sale, investor.publicKey),
+          vault: sale.vault,
+          investor: investor.publicKey,
+          treasury: await treasuryOf(),
+        })
+        .signers([investor])
+        .rpc();
+      assert.isNull(await connection.getAccountInfo(refunded));
+    });
+  });
+
+  describe("vault token account authority", () => {
+    it("rejects a vault ATA owned by another authority", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      });
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(LAMPORTS_PER_SOL)
+      );
+
+      // A real token account for the sale mint, but not the vault PDA's
+      const decoy = {
+        ...sale,
+        vaultTokenAccount: getAssociatedTokenAddressSync(
+          sale.mint,
+          sale.registrant.publicKey
+        ),
+      };
+      await expectError(
+        purchase(decoy, round, investor, new BN(LAMPORTS_PER_SOL)),
+        "ConstraintTokenOwner"
+      );
+      await expectError(
+        claim(decoy, investor, vesting),
+        "ConstraintTokenOwner"
+      );
+    });
+  });
+
+  describe("tokens_claimed invariant", () => {
+    it("stops claims beyond deposits even if the vault holds more", async () => {
+      // Two rounds oversell a single deposited token
+      const oneToken = new BN(LAMPORTS_PER_SOL);
+      const sale = await createSale({ deposit: oneToken });
+      const opts = {
+        tokensAvailable: oneToken,
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      };
+      const first = await addRound(sale, opts);
+      const second = await addRound(sale, opts);
+      const investor = await newInvestor(sale);
+      const paid = await purchase(sale, first, investor, oneToken);
+      const unbacked = await purchase(sale, second, investor, oneToken);
+
+      // Tokens sent straight to the vault aren't deposits, so the balance
+      // check passes and only the invariant stands in the way
+      await mintTo(
+        connection,
+        sale.registrant,
+        sale.mint,
+        sale.vaultTokenAccount,
+        sale.registrant,
+        BigInt(oneToken.toString())
+      );
+
+      await claim(sale, investor, paid);
+      await expectError(
+        claim(sale, investor, unbacked),
+        "ClaimsExceedDeposits"
+      );
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.tokensClaimed.toString(), oneToken.toString());
+    });
+  });
+
+  describe("refund window", () => {
+    it("rejects windows shorter than the launchpad minimum", async () => {
+      const sale = await createSale();
+      await expectError(setRefundWindow(sale, 86400), "InvalidRefundWindow");
+      await setRefundWindow(sale, 7 * 86400);
+    });
+
+    it("allows refunds only within the window after failure", async () => {
+      const { sale, round } = await createFailingSale();
+      await withoutMinRefundWindow(() => setRefundWindow(sale, 3));
+      const early = await newInvestor(sale);
+      const late = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      await purchase(sale, round, early, amount);
+      await purchase(sale, round, late, amount);
+      await sleep(4000);
+      await finalize(sale);
+
+      await refund(sale, early);
+      await sleep(4000);
+      await expectError(refund(sale, late), "RefundWindowClosed");
+    });
+  });
+
+  describe("avg_price", () => {
+    it("blends the cost of purchases across rounds", async () => {
+      const sale = await createSale();
+      const full = await addRound(sale);
+      const half = await addRound(sale, {
+        price: new BN(LAMPORTS_PER_SOL / 2),
+      });
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      // One token, then two more at half the price: 2 SOL for 3 tokens
+      await purchase(sale, full, investor, amount);
+      await purchase(sale, half, investor, amount);
+
+      const contribution = contributionPda(sale, investor.publicKey);
+      const state = await program.account.contribution.fetch(contribution);
+      assert.equal(
+        state.totalSpent.toString(),
+        (2 * LAMPORTS_PER_SOL).toString()
+      );
+      assert.equal(
+        state.totalTokens.toString(),
+        (3 * LAMPORTS_PER_SOL).toString()
+      );
+      const avg = await program.methods
+        .avgPrice()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          contribution,
+        })
+        .view();
+      assert.equal(avg.toString(), "666666666");
+    });
+  });
+
+  describe("round vaults", () => {
+    const roundVaultPda = (round: Keypair) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("round_vault"), round.publicKey.toBuffer()],
+        program.programId
+      )[0];
+
+    const enableRoundVault = async (sale: Sale, round: Keypair, cap: BN) => {
+      await program.methods
+        .enableRoundVault(cap)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: round.publicKey,
+          roundVault: roundVaultPda(round),
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      return roundVaultPda(round);
+    };
+
+    it("refunds a failed round without touching another's vault", async () => {
+      const sale = await createSale();
+      const endTime = Math.floor(Date.now() / 1000) + 3;
+      const failing = await addRound(sale, { endTime });
+      const succeeding = await addRound(sale, { endTime });
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const failingVault = await enableRoundVault(
+        sale,
+        failing,
+        new BN(100 * LAMPORTS_PER_SOL)
+      );
+      const succeedingVault = await enableRoundVault(sale, succeeding, amount);
+
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(sale, failing, investor, amount, {
+        roundVault: failingVault,
+      });
+      await purchase(sale, succeeding, investor, amount, {
+        roundVault: succeedingVault,
+      });
+      // Nothing from either round lands in the sale vault
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(
+        state.isolatedRaised.toString(),
+        state.totalRaised.toString()
+      );
+      assert.equal(state.withdrawn.toNumber(), 0);
+
+      const refundRound = () =>
+        program.methods
+          .refundRoundPurchase()
+          .accountsPartial({
+            launchpad: launchpad.publicKey,
+            tokenSale: sale.tokenSale.publicKey,
+            saleRound: failing.publicKey,
+            vesting,
+            contribution: contributionPda(sale, investor.publicKey),
+            roundVault: failingVault,
+            investor: investor.publicKey,
+            treasury: await treasuryOf(),
+          })
+          .signers([investor])
+          .rpc();
+      await expectError(refundRound(), "RoundNotFailed");
+      await sleep(4000);
+
+      const before = await connection.getBalance(succeedingVault);
+      const failingBefore = await connection.getBalance(failingVault);
+      await refundRound();
+      assert.equal(await connection.getBalance(succeedingVault), before);
+      assert.equal(
+        failingBefore - (await connection.getBalance(failingVault)),
+        LAMPORTS_PER_SOL
+      );
+      assert.isNull(await connection.getAccountInfo(vesting));
+    });
+
+    it("streams sale-vault proceeds and keeps funded rounds open", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setWithdrawStream(new BN(1))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const endTime = Math.floor(Date.now() / 1000) + 3;
+      const shared = await addRound(sale, { endTime });
+      const isolated = await addRound(sale, { endTime });
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const roundVault = await enableRoundVault(sale, isolated, amount);
+      const investor = await newInvestor(sale);
+      await purchase(sale, shared, investor, amount.muln(2));
+      await purchase(sale, isolated, investor, amount, { roundVault });
+
+      await sleep(4000);
+      await finalize(sale);
+      await sleep(2000);
+      await program.methods
+        .withdrawStreamed()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          vault: sale.vault,
+          registrant: sale.registrant.publicKey,
+          custody: null,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(state.withdrawn.toString(), amount.muln(2).toString());
+
+      const closeRound = () =>
+        program.methods
+          .closeRound()
+          .accountsPartial({
+            tokenSale: sale.tokenSale.publicKey,
+            saleRound: isolated.publicKey,
+            registrant: sale.registrant.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+      await expectError(closeRound(), "RoundVaultNotEmpty");
+      await program.methods
+        .withdrawRound()
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          saleRound: isolated.publicKey,
+          roundVault,
+          registrant: sale.registrant.publicKey,
+          custody: null,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      await closeRound();
+      assert.isNull(await connection.getAccountInfo(isolated.publicKey));
+    });
+
+    it("sweeps an abandoned round's vault to the treasury", async () => {
+      const { sale, round } = await createFailingSale(8);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      const roundVault = await enableRoundVault(sale, round, amount);
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, amount, { roundVault });
+      await withoutMinRefundWindow(() => setRefundWindow(sale, 3));
+      const { endTime } = await program.account.saleRound.fetch(
+        round.publicKey
+      );
+      await sleep(endTime.toNumber() * 1000 - Date.now() + 1000);
+      await finalize(sale);
+      await sleep(4000);
+
+      await expectError(reclaim(sale, [round]), "RoundAccountsMismatch");
+      const swept = await connection.getBalance(roundVault);
+      const events = await eventsFrom(
+        await reclaim(sale, [round], [roundVault])
+      );
+
+      const event = events.find((e) => e.name === "abandonedSaleReclaimed");
+      assert.equal(event.data.roundLamports.toNumber(), swept);
+      assert.isNull(await connection.getAccountInfo(roundVault));
+    });
+  });
+
+  describe("mid-sale top-ups", () => {
+    it("adds deposited tokens to an active round's inventory", async () => {
+      const oneToken = new BN(LAMPORTS_PER_SOL);
+      const sale = await createSale({ deposit: oneToken });
+      const round = await addRound(sale, { tokensAvailable: oneToken });
+      const investor = await newInvestor(sale);
+      await purchase(sale, round, investor, oneToken);
+      await expectError(
+        purchase(sale, round, investor, oneToken),
+        "RoundSoldOut"
+      );
+
+      const registrantTokenAccount = getAssociatedTokenAddressSync(
+        sale.mint,
+        sale.registrant.publicKey
+      );
+      await mintTo(
+        connection,
+        sale.registrant,
+        sale.mint,
+        registrantTokenAccount,
+        sale.registrant,
+        BigInt(oneToken.toString())
+      );
+      const topUp = () =>
+        program.methods
+          .depositSaleTokens(oneToken)
+          .accountsPartial({
+            tokenSale: sale.tokenSale.publicKey,
+            vault: sale.vault,
+            tokenMint: sale.mint,
+            vaultTokenAccount: sale.vaultTokenAccount,
+            registrantTokenAccount,
+            registrant: sale.registrant.publicKey,
+            saleRound: round.publicKey,
+          })
+          .signers([sale.registrant])
+          .rpc();
+      await expectError(topUp(), "ActiveTopUpBlocked");
+      await program.methods
+        .setActiveTopupAllowed(true)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      await topUp();
+
+      const before = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(before.tokensAvailable.toString(), oneToken.toString());
+      await purchase(sale, round, investor, oneToken);
+      const after = await program.account.saleRound.fetch(round.publicKey);
+      assert.equal(after.tokensAvailable.toNumber(), 0);
+      assert.equal(after.tokensSold.toString(), oneToken.muln(2).toString());
+      const state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.equal(
+        state.tokensDeposited.toString(),
+        oneToken.muln(2).toString()
+      );
+    });
+  });
+
+  describe("single active round policy", () => {
+    it("rejects activating a second round while one is live", async () => {
+      const sale = await createSale();
+      await program.methods
+        .setAllowOverlappingRounds(false)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const first = await addRound(sale);
+      const second = await addRound(sale, { activate: false });
+
+      await expectError(setRoundActive(sale, second, true), "RoundOverlap");
+      await setRoundActive(sale, first, false);
+      await setRoundActive(sale, second, true);
+    });
+  });
+
+  describe("max_claim_per_tx", () => {
+    it("splits a large claim across transactions", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      });
+      const cap = new BN(1.5 * LAMPORTS_PER_SOL);
+      await program.methods
+        .setMaxClaimPerTx(cap)
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const investor = await newInvestor(sale);
+      const vesting = await purchase(
+        sale,
+        round,
+        investor,
+        new BN(2 * LAMPORTS_PER_SOL)
+      );
+
+      await claim(sale, investor, vesting);
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        cap.toString()
+      );
+      await claim(sale, investor, vesting);
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        (2 * LAMPORTS_PER_SOL).toString()
+      );
+      await expectError(claim(sale, investor, vesting), "NothingToClaim");
+    });
+  });
+
+  describe("ata_payer", () => {
+    it("has the configured sponsor pay for the investor's ATA", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      await program.methods
+        .setAtaPayer({ registrant: {} })
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      // No ATA yet, unlike newInvestor
+      const investor = Keypair.generate();
+      await airdrop(investor.publicKey);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      await expectError(
+        purchase(sale, round, investor, amount),
+        "InvalidAtaSponsor"
+      );
+      const before = await connection.getBalance(sale.registrant.publicKey);
+      await purchase(sale, round, investor, amount, {
+        ataSponsor: sale.registrant,
+      });
+      const after = await connection.getBalance(sale.registrant.publicKey);
+      const rent = await connection.getMinimumBalanceForRentExemption(165);
+      assert.equal(before - after, rent);
+      assert.isNotNull(
+        await connection.getAccountInfo(
+          getAssociatedTokenAddressSync(sale.mint, investor.publicKey)
+        )
+      );
+    });
+  });
+
+  describe("canonical vesting addresses", () => {
+    it("claims against a schedule derived client-side", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      });
+      const other = await addRound(sale);
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+      await purchase(sale, other, investor, amount);
+      await purchase(sale, round, investor, amount);
+
+      // Sale, round, investor and the contribution's nonce at purchase time
+      const vesting = vestingPda(sale, round, investor.publicKey, 1);
+      const state = await program.account.vestingSchedule.fetch(vesting);
+      assert.ok(state.saleRound.equals(round.publicKey));
+      assert.ok(state.seedInvestor.equals(investor.publicKey));
+      assert.equal(state.nonce.toNumber(), 1);
+
+      await claim(sale, investor, vesting);
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        amount.toString()
+      );
+    });
+  });
+
+  describe("partial claims", () => {
+    it("claims part of the vested amount, then the remainder", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale, {
+        vesting: { ...defaultVesting, tgeBps: 10_000 },
+      });
+      const investor = await newInvestor(sale);
+      const total = new BN(2 * LAMPORTS_PER_SOL);
+      const vesting = await purchase(sale, round, investor, total);
+      const part = new BN(LAMPORTS_PER_SOL / 4);
+
+      await claim(sale, investor, vesting, { amount: part });
+      let state = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(state.released.toString(), part.toString());
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        part.toString()
+      );
+
+      await claim(sale, investor, vesting);
+      state = await program.account.vestingSchedule.fetch(vesting);
+      assert.equal(state.released.toString(), total.toString());
+      assert.equal(
+        (await tokenBalance(sale, investor.publicKey)).toString(),
+        total.toString()
+      );
+    });
+  });
+
+  describe("velocity circuit breaker", () => {
+    it("winds the sale down once inflows exceed the limit", async () => {
+      const sale = await createSale();
+      const round = await addRound(sale);
+      await program.methods
+        .setVelocityLimit(new BN(1.5 * LAMPORTS_PER_SOL), new BN(3600))
+        .accountsPartial({
+          tokenSale: sale.tokenSale.publicKey,
+          registrant: sale.registrant.publicKey,
+        })
+        .signers([sale.registrant])
+        .rpc();
+      const investor = await newInvestor(sale);
+      const amount = new BN(LAMPORTS_PER_SOL);
+
+      await purchase(sale, round, investor, amount);
+      let state = await program.account.tokenSale.fetch(
+        sale.tokenSale.publicKey
+      );
+      assert.deepEqual(state.state, { open: {} });
+
+      // The purchase that trips the breaker still goes through
+      await purchase(sale, round, investor, amount);
+      state = await program.account.tokenSale.fetch(sale.tokenSale.publicKey);
+      assert.deepEqual(state.state, { windingDown: {} });
+      assert.equal(
+        state.windowRaised.toString(),
+        (2 * LAMPORTS_PER_SOL).toString()
+      );
+      await expectError(
+        purchase(sale, round, investor, amount),
+        "SaleWindingDown"
+      );
+    });
   });
 });